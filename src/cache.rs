@@ -0,0 +1,370 @@
+use crate::config::CacheConfig;
+use crate::store;
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use octocrab::models::repos::RepoCommit;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A detailed commit (message, files and patches) as downloaded from GitHub
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedCommit {
+    /// The repository (owner/name) the commit was downloaded from
+    pub repository: String,
+    pub commit: RepoCommit,
+}
+
+/// Just enough of a cached commit to sort it, the rest is skipped while parsing
+#[derive(Deserialize)]
+struct DatedCommit {
+    repository: String,
+    commit: DatedRepoCommit,
+}
+
+#[derive(Deserialize)]
+struct DatedRepoCommit {
+    sha: String,
+    commit: DatedCommitDetails,
+}
+
+#[derive(Deserialize)]
+struct DatedCommitDetails {
+    author: Option<DatedAuthor>,
+}
+
+#[derive(Deserialize)]
+struct DatedAuthor {
+    date: Option<DateTime<Utc>>,
+}
+
+/// The repository (owner/name), hash and authoring date of a cached commit
+pub type DatedKey = (String, String, Option<DateTime<Utc>>);
+
+/// On-disk cache of detailed commits, keyed by their repository and hash
+#[derive(Debug, Clone)]
+pub struct CommitCache {
+    path: PathBuf,
+    max_size: u64,
+    max_age: Duration,
+}
+
+impl CommitCache {
+    const CACHE_DIRNAME: &'static str = "cache";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the cache directory path based on the operating system
+    pub fn get_cache_path() -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find data directory")?;
+
+        let cache_dir = proj_dirs.data_dir().join(Self::CACHE_DIRNAME);
+        fs::create_dir_all(&cache_dir)?;
+
+        Ok(cache_dir)
+    }
+
+    /// Opens the cache at the default location, returns None if it is disabled
+    pub fn open(config: &CacheConfig) -> Result<Option<Self>, Box<dyn Error>> {
+        if !config.enabled() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            path: Self::get_cache_path()?,
+            max_size: config.max_size()?,
+            max_age: Duration::from_secs(config.max_age()?),
+        }))
+    }
+
+    /// Where the commit with the given hash is (or would be) stored, the same
+    /// commit may be found in several repositories (e.g. forks)
+    fn commit_path(&self, repository: &str, sha: &str) -> PathBuf {
+        let prefix = sha.get(..2).unwrap_or(sha);
+        self.path
+            .join(repository)
+            .join(prefix)
+            .join(format!("{}.json", sha))
+    }
+
+    /// Finds a cached commit of a repository (owner/name) by its (full) hash
+    pub fn get(&self, repository: &str, sha: &str) -> Option<CachedCommit> {
+        let contents = fs::read(self.commit_path(repository, sha)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Stores a detailed commit of a repository (owner/name)
+    pub fn put(&self, repository: &str, commit: &RepoCommit) -> Result<(), Box<dyn Error>> {
+        let path = self.commit_path(repository, &commit.sha);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let cached = CachedCommit {
+            repository: repository.to_owned(),
+            commit: commit.clone(),
+        };
+
+        store::write(path, serde_json::to_vec(&cached)?)
+    }
+
+    /// The repository (owner/name) and hash of all cached commits
+    pub fn keys(&self) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        Ok(self
+            .files(None)?
+            .iter()
+            .filter_map(|file| {
+                let sha = file.file_stem()?.to_string_lossy().into_owned();
+                let repository = file.parent()?.parent()?.strip_prefix(&self.path).ok()?;
+                // owner/name, whatever the separator of the platform
+                let repository: Vec<_> = repository
+                    .iter()
+                    .map(|part| part.to_string_lossy())
+                    .collect();
+                Some((repository.join("/"), sha))
+            })
+            .collect())
+    }
+
+    /// The repository (owner/name), hash and authoring date of the cached
+    /// commits, of a single repository or of all of them. Only the dates are
+    /// read, so that the commits can be sorted before they are loaded one by one
+    pub fn dated_keys(&self, repository: Option<&str>) -> Result<Vec<DatedKey>, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        for file in self.files(repository)? {
+            // skip unreadable entries, they'll be downloaded again when needed
+            let Some(dated) = fs::read(&file)
+                .ok()
+                .and_then(|contents| serde_json::from_slice::<DatedCommit>(&contents).ok())
+            else {
+                continue;
+            };
+            let date = dated.commit.commit.author.and_then(|author| author.date);
+            keys.push((dated.repository, dated.commit.sha, date));
+        }
+        Ok(keys)
+    }
+
+    /// The directories within a directory, none if it doesn't exist
+    fn subdirs(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut subdirs = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?.path();
+            if entry.is_dir() {
+                subdirs.push(entry);
+            }
+        }
+        Ok(subdirs)
+    }
+
+    /// Lists the files of the cached commits, of a single repository or of all
+    /// of them, laid out as `owner/name/prefix/sha.json`
+    fn files(&self, repository: Option<&str>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let repository_dirs = match repository {
+            Some(repository) => vec![self.path.join(repository)],
+            None => {
+                let mut repository_dirs = Vec::new();
+                for owner_dir in Self::subdirs(&self.path)? {
+                    repository_dirs.extend(Self::subdirs(&owner_dir)?);
+                }
+                repository_dirs
+            }
+        };
+
+        let mut files = Vec::new();
+        for repository_dir in repository_dirs {
+            for prefix_dir in Self::subdirs(&repository_dir)? {
+                for entry in fs::read_dir(&prefix_dir)? {
+                    let entry = entry?.path();
+                    if entry.extension().is_some_and(|ext| ext == "json") {
+                        files.push(entry);
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Removes the commits that are older than the maximum age, and then the
+    /// oldest commits until the cache fits the maximum size. Returns how many
+    /// commits have been removed
+    pub fn enforce_retention(&self) -> Result<usize, Box<dyn Error>> {
+        let now = SystemTime::now();
+        let mut entries: Vec<(PathBuf, SystemTime, u64)> = Vec::new();
+        for file in self.files(None)? {
+            let metadata = match fs::metadata(&file) {
+                Ok(metadata) => metadata,
+                // removed in the meantime, e.g. by another instance
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            entries.push((file, metadata.modified()?, metadata.len()));
+        }
+        // oldest first
+        entries.sort_by_key(|(_, modified, _)| *modified);
+
+        let mut removed = 0;
+        let mut total_size: u64 = entries.iter().map(|(_, _, size)| size).sum();
+        for (file, modified, size) in entries {
+            let expired = now.duration_since(modified).unwrap_or_default() > self.max_age;
+            let oversized = total_size > self.max_size;
+            if !expired && !oversized {
+                // entries are sorted by age, so the rest are both recent enough and fit
+                break;
+            }
+
+            self.remove_file(&file)?;
+            total_size -= size;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    fn remove_file(&self, file: &Path) -> Result<(), Box<dyn Error>> {
+        match fs::remove_file(file) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => (),
+        }
+        // drop the prefix, name and owner directories once they're empty
+        for dir in file.ancestors().skip(1).take(3) {
+            if dir == self.path || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::matcher::tests::repo_commit;
+    use std::fs::File;
+    use std::process;
+
+    /// A cache in its own temporary directory
+    pub fn cache(name: &str, max_size: u64, max_age: Duration) -> CommitCache {
+        let path = std::env::temp_dir().join(format!("vulngrep-cache-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        CommitCache {
+            path,
+            max_size,
+            max_age,
+        }
+    }
+
+    /// Removes a cached commit of a repository (owner/name)
+    pub fn remove(cache: &CommitCache, repository: &str, sha: &str) {
+        cache
+            .remove_file(&cache.commit_path(repository, sha))
+            .unwrap();
+    }
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    #[test]
+    fn gets_the_commits_it_puts() {
+        let cache = cache("get", u64::MAX, DAY);
+        let commit = repo_commit("abc123", "mm: fix a UAF", &[("mm/slab.c", Some("+UAF"))]);
+        assert!(cache.get("torvalds/linux", "abc123").is_none());
+
+        cache.put("torvalds/linux", &commit).unwrap();
+        let cached = cache.get("torvalds/linux", "abc123").unwrap();
+        assert_eq!(cached.repository, "torvalds/linux");
+        assert_eq!(cached.commit, commit);
+        assert_eq!(
+            cache.keys().unwrap(),
+            [("torvalds/linux".to_owned(), "abc123".to_owned())]
+        );
+        fs::remove_dir_all(&cache.path).unwrap();
+    }
+
+    #[test]
+    fn keys_the_commits_by_repository() {
+        let cache = cache("keys", u64::MAX, DAY);
+        let commit = repo_commit("abc123", "mm: fix a UAF", &[]);
+        cache.put("torvalds/linux", &commit).unwrap();
+        assert!(cache.get("alice/linux", "abc123").is_none());
+
+        // a fork keeps its own copy
+        cache.put("alice/linux", &commit).unwrap();
+        assert_eq!(
+            cache.get("alice/linux", "abc123").unwrap().repository,
+            "alice/linux"
+        );
+        assert_eq!(
+            cache.get("torvalds/linux", "abc123").unwrap().repository,
+            "torvalds/linux"
+        );
+        let mut keys = cache.keys().unwrap();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                ("alice/linux".to_owned(), "abc123".to_owned()),
+                ("torvalds/linux".to_owned(), "abc123".to_owned()),
+            ]
+        );
+        assert_eq!(cache.dated_keys(Some("alice/linux")).unwrap().len(), 1);
+        fs::remove_dir_all(&cache.path).unwrap();
+    }
+
+    /// Makes a cached commit look like it was downloaded some time ago
+    fn age(cache: &CommitCache, sha: &str, age: Duration) {
+        let file = File::options()
+            .write(true)
+            .open(cache.commit_path("torvalds/linux", sha))
+            .unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn removes_the_expired_commits() {
+        let cache = cache("age", u64::MAX, DAY);
+        cache
+            .put("torvalds/linux", &repo_commit("abc123", "old", &[]))
+            .unwrap();
+        cache
+            .put("torvalds/linux", &repo_commit("def456", "new", &[]))
+            .unwrap();
+        age(&cache, "abc123", 2 * DAY);
+
+        assert_eq!(cache.enforce_retention().unwrap(), 1);
+        assert!(cache.get("torvalds/linux", "abc123").is_none());
+        assert!(cache.get("torvalds/linux", "def456").is_some());
+        fs::remove_dir_all(&cache.path).unwrap();
+    }
+
+    #[test]
+    fn removes_the_oldest_commits_beyond_the_size() {
+        let mut cache = cache("size", u64::MAX, DAY);
+        for (sha, hours) in [("abc123", 3), ("def456", 2), ("fed789", 1)] {
+            cache
+                .put("torvalds/linux", &repo_commit(sha, "commit", &[]))
+                .unwrap();
+            age(&cache, sha, Duration::from_secs(hours * 3600));
+        }
+        let size = fs::metadata(cache.commit_path("torvalds/linux", "abc123"))
+            .unwrap()
+            .len();
+        cache.max_size = 2 * size;
+
+        assert_eq!(cache.enforce_retention().unwrap(), 1);
+        assert!(cache.get("torvalds/linux", "abc123").is_none());
+        assert_eq!(cache.keys().unwrap().len(), 2);
+
+        // the directories are dropped with the last commit
+        cache.max_size = 0;
+        assert_eq!(cache.enforce_retention().unwrap(), 2);
+        assert_eq!(fs::read_dir(&cache.path).unwrap().count(), 0);
+        fs::remove_dir_all(&cache.path).unwrap();
+    }
+}
@@ -1,13 +1,36 @@
+use crate::logging::LoggingConfig;
+use crate::metrics::MetricsConfig;
+use crate::notifier::{NotifierConfig, ThrottleConfig};
+use crate::server::ServerConfig;
+use crate::store;
+use chrono::{DateTime, TimeDelta, Utc};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 
+/// How urgent the matches of a rule are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct File {
     path: String,
-    pattern: Option<Vec<String>>
+    pattern: Option<Vec<String>>,
+    /// Names of the notifiers this file's matches go to, overrides the notification's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notify: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severity: Option<Severity>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,7 +43,25 @@ pub struct Repository {
 pub struct Notification {
     repository: Repository,
     files: Option<Vec<File>>,
-    pattern: Option<Vec<String>>
+    pattern: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backfill: Option<String>,
+    /// Email addresses receiving the matches of this notification, instead of
+    /// the recipients of the email sinks it routes to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recipients: Option<Vec<String>>,
+    /// Names of the notifiers the matches go to, all of them when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notify: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severity: Option<Severity>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    enabled: Option<bool>,
+    max_size: Option<String>,
+    max_age: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,6 +69,18 @@ pub struct AppConfig {
     interval: Option<String>,
     github_token: String,
     notifications: Vec<Notification>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notifiers: Option<Vec<NotifierConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    throttle: Option<ThrottleConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logging: Option<LoggingConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metrics: Option<MetricsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server: Option<ServerConfig>,
 }
 
 impl File {
@@ -37,6 +90,23 @@ impl File {
     pub fn pattern(&self) -> Option<Vec<String>> {
         self.pattern.clone()
     }
+    pub fn notify(&self) -> Option<&Vec<String>> {
+        self.notify.as_ref()
+    }
+    pub fn severity(&self) -> Option<Severity> {
+        self.severity
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        })
+    }
 }
 
 impl Repository {
@@ -70,6 +140,120 @@ impl Notification {
     pub fn patterns(&self) -> Option<Vec<String>> {
         self.pattern.clone()
     }
+    /// How many seconds of history to scan when the repository is first added
+    pub fn backfill(&self) -> Result<Option<u64>, String> {
+        self.backfill.as_deref().map(parse_duration).transpose()
+    }
+    pub fn recipients(&self) -> Option<&Vec<String>> {
+        self.recipients.as_ref()
+    }
+    pub fn notify(&self) -> Option<&Vec<String>> {
+        self.notify.as_ref()
+    }
+    /// Whether some of the matches may go to the notifier, through the routes
+    /// of the notification or of one of its files
+    pub fn routes_to(&self, notifier: &str) -> bool {
+        let listed = |names: &Vec<String>| names.iter().any(|name| name == notifier);
+        self.notify().is_none_or(listed)
+            || self
+                .files()
+                .iter()
+                .flatten()
+                .filter_map(|file| file.notify())
+                .any(listed)
+    }
+    /// How urgent the matches are (defaults to medium)
+    pub fn severity(&self) -> Severity {
+        self.severity.unwrap_or_default()
+    }
+}
+
+/// Parses a duration such as `30d`, `6h`, `15m` or `10s` into seconds
+pub fn parse_duration(duration_str: &str) -> Result<u64, String> {
+    let Some(unit_index) = duration_str.char_indices().last().map(|(index, _)| index) else {
+        return Err("Empty duration".to_owned());
+    };
+    let (value, unit) = duration_str.split_at(unit_index);
+
+    let numeric_value: u64 = match value.parse() {
+        Ok(num) => num,
+        Err(_) => return Err(format!("Invalid numeric value in duration: {}", value)),
+    };
+
+    let multiplier = match unit {
+        "d" => 86400, // days to seconds
+        "h" => 3600,  // hours to seconds
+        "m" => 60,    // minutes to seconds
+        "s" => 1,
+        _ => {
+            return Err(format!(
+                "Invalid time unit: {}. Use 'd' for days, 'h' for hours, 'm' for minutes or 's' for seconds.",
+                unit
+            ))
+        }
+    };
+    numeric_value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Duration too long: {}", duration_str))
+}
+
+/// The date a duration (in seconds) ago
+pub fn seconds_ago(secs: u64) -> Result<DateTime<Utc>, String> {
+    i64::try_from(secs)
+        .ok()
+        .and_then(TimeDelta::try_seconds)
+        .and_then(|delta| Utc::now().checked_sub_signed(delta))
+        .ok_or_else(|| format!("Duration too long: {}s", secs))
+}
+
+/// Parses a size such as `500MB`, `1GB` or `64KB` into bytes
+pub fn parse_size(size_str: &str) -> Result<u64, String> {
+    let size_str = size_str.trim().to_ascii_uppercase();
+    let unit_index = size_str
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size_str.len());
+    let (value, unit) = size_str.split_at(unit_index);
+
+    let numeric_value: u64 = match value.parse() {
+        Ok(num) => num,
+        Err(_) => return Err(format!("Invalid numeric value in size: {}", value)),
+    };
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        unit => {
+            return Err(format!(
+                "Invalid size unit: {}. Use 'KB', 'MB' or 'GB'.",
+                unit
+            ))
+        }
+    };
+    numeric_value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size is too large: {}", size_str))
+}
+
+impl CacheConfig {
+    const DEFAULT_MAX_SIZE: &'static str = "1GB";
+    const DEFAULT_MAX_AGE: &'static str = "90d";
+
+    /// Whether downloaded commits are cached at all (defaults to true)
+    pub fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Maximum size of the cache in bytes
+    pub fn max_size(&self) -> Result<u64, String> {
+        parse_size(self.max_size.as_deref().unwrap_or(Self::DEFAULT_MAX_SIZE))
+    }
+
+    /// Maximum age of a cached commit in seconds
+    pub fn max_age(&self) -> Result<u64, String> {
+        parse_duration(self.max_age.as_deref().unwrap_or(Self::DEFAULT_MAX_AGE))
+    }
 }
 
 impl AppConfig {
@@ -78,24 +262,6 @@ impl AppConfig {
     const ORGANIZATION: &'static str = "wildbit";
     const APPLICATION: &'static str = "vulngrep";
 
-    fn parse_interval(&self, interval_str: &str) -> Result<u64, String> {
-        let (value, unit) = interval_str.split_at(interval_str.len() - 1);
-    
-        let numeric_value: u64 = match value.parse() {
-            Ok(num) => num,
-            Err(_) => return Err(format!("Invalid numeric value in interval: {}", value)),
-        };
-    
-        match unit {
-            "h" => Ok(numeric_value * 3600), // hours to seconds
-            "m" => Ok(numeric_value * 60),   // minutes to seconds
-            _ => Err(format!(
-                "Invalid time unit: {}. Use 'h' for hours or 'm' for minutes.",
-                unit
-            )),
-        }
-    }
-
     /// Get the configuration file path based on the operating system
     pub fn get_config_path() -> Result<PathBuf, Box<dyn Error>> {
         let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
@@ -129,18 +295,18 @@ impl AppConfig {
     pub fn save(&self) -> Result<(), Box<dyn Error>> {
         let config_path = Self::get_config_path()?;
         let contents = serde_yaml::to_string(self)?;
-        fs::write(config_path, contents)?;
+        store::write(config_path, contents)?;
         Ok(())
     }
 
     /// Returns None for one-time execution, or how many seconds to sleep between cycles
     pub fn interval(&self) -> Option<u64> {
-        self.interval.as_ref().and_then(|interval_str| {
-            match self.parse_interval(interval_str) {
-                Ok(interval) => Some(interval),
+        self.interval.as_ref().map(|interval_str| {
+            match parse_duration(interval_str) {
+                Ok(interval) => interval,
                 Err(err) => {
                     eprintln!("{}", err);
-                    Some(60) // default to 60 secs
+                    60 // default to 60 secs
                 }
             }
         })
@@ -148,12 +314,52 @@ impl AppConfig {
 
     /// The GitHub token
     pub fn token(&self) -> &String {
-        return &self.github_token;
+        &self.github_token
     }
 
     /// The user-defined notifications
     pub fn notifications(&self) -> &Vec<Notification> {
-        return &self.notifications
+        &self.notifications
+    }
+
+    /// The settings of the local commit cache
+    pub fn cache(&self) -> CacheConfig {
+        self.cache.clone().unwrap_or_default()
+    }
+
+    /// The sinks matches are delivered to, the terminal and desktop by default
+    pub fn notifiers(&self) -> Vec<NotifierConfig> {
+        self.notifiers
+            .clone()
+            .unwrap_or_else(NotifierConfig::defaults)
+    }
+
+    /// The limits on the delivery of the matches, none by default
+    pub fn throttle(&self) -> ThrottleConfig {
+        self.throttle.clone().unwrap_or_default()
+    }
+
+    /// Where the diagnostics are written, nowhere by default
+    pub fn logging(&self) -> Option<&LoggingConfig> {
+        self.logging.as_ref()
+    }
+
+    /// Where the watcher serves its metrics, nowhere by default
+    pub fn metrics(&self) -> Option<&MetricsConfig> {
+        self.metrics.as_ref()
+    }
+
+    /// Where `vulngrep serve` serves the matches
+    pub fn server(&self) -> ServerConfig {
+        self.server.clone().unwrap_or_default()
+    }
+
+    /// The user-defined notifications of a single repository (owner/name)
+    pub fn notifications_for(&self, uri: &str) -> Vec<&Notification> {
+        self.notifications
+            .iter()
+            .filter(|notification| notification.repository().uri() == uri)
+            .collect()
     }
 }
 
@@ -163,6 +369,63 @@ impl Default for AppConfig {
             interval: None,
             github_token: "GITHUB_TOKEN".to_owned(),
             notifications: vec![],
+            cache: None,
+            notifiers: None,
+            throttle: None,
+            logging: None,
+            metrics: None,
+            server: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30d"), Ok(30 * 86400));
+        assert_eq!(parse_duration("6h"), Ok(6 * 3600));
+        assert_eq!(parse_duration("15m"), Ok(15 * 60));
+        assert_eq!(parse_duration("10s"), Ok(10));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("10w").is_err());
+        assert!(parse_duration("-1d").is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert!(parse_duration("99999999999999999d").is_err());
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+        assert_eq!(parse_duration(&format!("{}s", u64::MAX)), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn rejects_dates_too_far_in_the_past() {
+        assert!(seconds_ago(86400).is_ok());
+        assert!(seconds_ago(200_000_000 * 86400).is_err());
+        assert!(seconds_ago(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("64KB"), Ok(64 << 10));
+        assert_eq!(parse_size("500mb"), Ok(500 << 20));
+        assert_eq!(parse_size("1GB"), Ok(1 << 30));
+        assert_eq!(parse_size("42"), Ok(42));
+        assert!(parse_size("1TB").is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_sizes() {
+        assert!(parse_size("17179869184GB").is_err());
+        assert!(parse_size(&format!("{}KB", u64::MAX)).is_err());
+        assert_eq!(parse_size(&format!("{}", u64::MAX)), Ok(u64::MAX));
+    }
+}
@@ -0,0 +1,407 @@
+use crate::matcher::make_pattern;
+use std::error::Error;
+use std::str::FromStr;
+
+/// Lines of context kept around every matching line of an excerpt
+const CONTEXT_LINES: usize = 2;
+/// Upper bound on the length of an excerpt, in lines
+const MAX_EXCERPT_LINES: usize = 20;
+
+/// Extracts the lines of a patch that match any of the patterns, along with a
+/// few lines of context. Groups of lines that are far apart are separated by
+/// `...`. When no single line matches (e.g. the file matched by path alone),
+/// the beginning of the patch is used instead.
+pub fn excerpt(patch: &str, patterns: &[String]) -> Option<String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let wild_patterns: Vec<_> = patterns.iter().map(make_pattern).collect();
+    let matching: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        // hunk headers carry the function name, which isn't part of the change
+        .filter(|(_, line)| !line.starts_with("@@"))
+        .filter(|(_, line)| wild_patterns.iter().any(|pattern| pattern.matches(line)))
+        .map(|(index, _)| index)
+        .collect();
+
+    if matching.is_empty() {
+        let head: Vec<&str> = lines.into_iter().take(MAX_EXCERPT_LINES).collect();
+        return Some(head.join("\n"));
+    }
+
+    let mut excerpt: Vec<&str> = Vec::new();
+    let mut last_included: Option<usize> = None;
+    for index in matching {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES).min(lines.len() - 1);
+        let start = match last_included {
+            Some(last) if last >= end => continue,
+            Some(last) if last + 1 >= start => last + 1,
+            Some(_) => {
+                excerpt.push("...");
+                start
+            }
+            None => start,
+        };
+
+        excerpt.extend(&lines[start..=end]);
+        last_included = Some(end);
+        if excerpt.len() >= MAX_EXCERPT_LINES {
+            excerpt.truncate(MAX_EXCERPT_LINES);
+            excerpt.push("...");
+            break;
+        }
+    }
+
+    Some(excerpt.join("\n"))
+}
+
+/// Parses the start of the new side of a hunk header, e.g. `@@ -10,6 +12,7 @@`
+fn hunk_start(header: &str) -> Option<u64> {
+    let new_side = header
+        .split_whitespace()
+        .find(|part| part.starts_with('+'))?;
+    let start = new_side[1..].split(',').next()?;
+    start.parse().ok()
+}
+
+/// Line numbers, in the new version of the file, of the patch lines that match
+/// any of the patterns. Removed lines get the number of the line that took
+/// their place. When no single line matches (e.g. the file matched by path
+/// alone), the first changed line is used instead.
+pub fn matching_lines(patch: &str, patterns: &[String]) -> Vec<u64> {
+    let wild_patterns: Vec<_> = patterns.iter().map(make_pattern).collect();
+    let mut matching = Vec::new();
+    let mut first_change = None;
+    let mut line_number = 0;
+
+    for line in patch.lines() {
+        if line.starts_with("@@") {
+            line_number = hunk_start(line).unwrap_or(line_number);
+            continue;
+        }
+
+        let number = line_number;
+        match line.chars().next() {
+            Some('+') | Some(' ') | None => line_number += 1,
+            Some('-') => (),
+            // e.g. "\ No newline at end of file"
+            _ => continue,
+        }
+        if line.starts_with(['+', '-']) && first_change.is_none() {
+            first_change = Some(number);
+        }
+        if wild_patterns.iter().any(|pattern| pattern.matches(line)) && !matching.contains(&number)
+        {
+            matching.push(number);
+        }
+    }
+
+    if matching.is_empty() {
+        matching.extend(first_change);
+    }
+    matching
+}
+
+/// How much of the patch of a matching file is shown in the terminal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffContext {
+    /// The file name, stats and patterns only
+    Off,
+    /// The matching lines, along with this many lines of context
+    Lines(usize),
+    /// The whole hunks with a matching line
+    Hunks,
+}
+
+impl Default for DiffContext {
+    fn default() -> Self {
+        Self::Lines(CONTEXT_LINES)
+    }
+}
+
+impl FromStr for DiffContext {
+    type Err = Box<dyn Error>;
+
+    fn from_str(context: &str) -> Result<Self, Self::Err> {
+        match context {
+            "off" => Ok(Self::Off),
+            "hunks" => Ok(Self::Hunks),
+            lines => lines.parse().map(Self::Lines).map_err(|_| {
+                format!(
+                    "Invalid diff context {}, expected off, hunks or a number of lines",
+                    context
+                )
+                .into()
+            }),
+        }
+    }
+}
+
+/// A line of a patch
+#[derive(Debug, Clone)]
+pub struct DiffLine<'a> {
+    /// The line number in the new version of the file, none for removed lines
+    /// and for excerpts without hunk headers
+    pub number: Option<u64>,
+    pub text: &'a str,
+    /// Whether any of the patterns matches the line
+    pub matching: bool,
+}
+
+/// Consecutive lines of a patch
+#[derive(Debug, Clone)]
+pub struct Hunk<'a> {
+    /// The function the lines belong to, as found in the hunk header
+    pub function: Option<&'a str>,
+    pub lines: Vec<DiffLine<'a>>,
+}
+
+/// Splits a patch into its hunks, lines before the first hunk header (e.g. in
+/// an excerpt) form a hunk of their own
+fn parse_hunks<'a>(patch: &'a str, patterns: &[String]) -> Vec<Hunk<'a>> {
+    let wild_patterns: Vec<_> = patterns.iter().map(make_pattern).collect();
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut line_number = None;
+
+    for line in patch.lines() {
+        if line.starts_with("@@") {
+            line_number = hunk_start(line);
+            // the function name follows the second @@
+            let function = line
+                .get(2..)
+                .and_then(|header| header.split_once("@@"))
+                .map(|(_, function)| function.trim())
+                .filter(|function| !function.is_empty());
+            hunks.push(Hunk {
+                function,
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        if line == "..." {
+            // groups of an excerpt
+            continue;
+        }
+
+        let number = match line.chars().next() {
+            Some('-') => None,
+            Some('+') | Some(' ') | None => {
+                let number = line_number;
+                line_number = line_number.map(|number| number + 1);
+                number
+            }
+            // e.g. "\ No newline at end of file"
+            _ => continue,
+        };
+        if hunks.is_empty() {
+            hunks.push(Hunk {
+                function: None,
+                lines: Vec::new(),
+            });
+        }
+        if let Some(hunk) = hunks.last_mut() {
+            hunk.lines.push(DiffLine {
+                number,
+                text: line,
+                matching: wild_patterns.iter().any(|pattern| pattern.matches(line)),
+            });
+        }
+    }
+    hunks
+}
+
+/// The parts of a patch worth showing: the hunks with a matching line, cut
+/// down to the matching lines and their context unless whole hunks are asked
+/// for. When no single line matches, the first change is shown instead.
+pub fn hunks<'a>(patch: &'a str, patterns: &[String], context: DiffContext) -> Vec<Hunk<'a>> {
+    let mut hunks = parse_hunks(patch, patterns);
+    let any_matching = hunks
+        .iter()
+        .flat_map(|hunk| &hunk.lines)
+        .any(|line| line.matching);
+    let is_shown = |line: &DiffLine| {
+        if any_matching {
+            line.matching
+        } else {
+            line.text.starts_with(['+', '-'])
+        }
+    };
+    if !any_matching {
+        // only the first change stands in for the matching lines
+        hunks.retain(|hunk| hunk.lines.iter().any(is_shown));
+        hunks.truncate(1);
+    }
+
+    let context = match context {
+        DiffContext::Off => return Vec::new(),
+        DiffContext::Hunks => {
+            hunks.retain(|hunk| hunk.lines.iter().any(is_shown));
+            return hunks;
+        }
+        DiffContext::Lines(context) => context,
+    };
+
+    let mut groups = Vec::new();
+    let mut total = 0;
+    for hunk in hunks {
+        let shown: Vec<usize> = (0..hunk.lines.len())
+            .filter(|index| is_shown(&hunk.lines[*index]))
+            .take(if any_matching { usize::MAX } else { 1 })
+            .collect();
+
+        let mut group: Option<(usize, usize)> = None;
+        for index in shown {
+            let start = index.saturating_sub(context);
+            let end = (index + context).min(hunk.lines.len() - 1);
+            group = match group {
+                Some((group_start, group_end)) if start <= group_end + 1 => {
+                    Some((group_start, end.max(group_end)))
+                }
+                Some((group_start, group_end)) => {
+                    groups.push(Hunk {
+                        function: hunk.function,
+                        lines: hunk.lines[group_start..=group_end].to_vec(),
+                    });
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some((group_start, group_end)) = group {
+            groups.push(Hunk {
+                function: hunk.function,
+                lines: hunk.lines[group_start..=group_end].to_vec(),
+            });
+        }
+    }
+
+    // keeps the terminal readable, whole hunks are there when asked for
+    for group in &mut groups {
+        let room = MAX_EXCERPT_LINES.saturating_sub(total);
+        group.lines.truncate(room);
+        total += group.lines.len();
+    }
+    groups.retain(|group| !group.lines.is_empty());
+    groups
+}
+
+/// Where a pattern matches a line, in bytes. Wildcards are matched lazily, so
+/// the span is the shortest one starting at the first match of the pattern
+pub fn matched_span(line: &str, pattern: &str) -> Option<(usize, usize)> {
+    let line = line.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    let pieces: Vec<&str> = pattern
+        .split(['*', '?'])
+        .filter(|piece| !piece.is_empty())
+        .collect();
+    let (first, rest) = pieces.split_first()?;
+
+    line.match_indices(first).find_map(|(start, _)| {
+        let mut end = start + first.len();
+        for piece in rest {
+            end += line[end..].find(piece)? + piece.len();
+        }
+        Some((start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "@@ -10,6 +10,7 @@ static void free_obj(struct obj *o)
+ \tlock(o);
+ \ta();
+ \tb();
+-\tkfree(o);
++\tkfree_rcu(o);
++\to = NULL;
+ \tunlock(o);
+@@ -40,3 +41,3 @@ int other(void)
+ \tx();
+-\ty();
++\tz();";
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    fn texts<'a>(hunk: &Hunk<'a>) -> Vec<&'a str> {
+        hunk.lines.iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn keeps_the_matching_lines_and_their_context() {
+        let hunks = hunks(PATCH, &patterns(&["kfree"]), DiffContext::Lines(1));
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(
+            hunks[0].function,
+            Some("static void free_obj(struct obj *o)")
+        );
+        assert_eq!(
+            texts(&hunks[0]),
+            [
+                " \tb();",
+                "-\tkfree(o);",
+                "+\tkfree_rcu(o);",
+                "+\to = NULL;"
+            ]
+        );
+        let numbers: Vec<Option<u64>> = hunks[0].lines.iter().map(|line| line.number).collect();
+        assert_eq!(numbers, [Some(12), None, Some(13), Some(14)]);
+        let matching: Vec<bool> = hunks[0].lines.iter().map(|line| line.matching).collect();
+        assert_eq!(matching, [false, true, true, false]);
+    }
+
+    #[test]
+    fn splits_the_lines_far_apart() {
+        let hunks = hunks(PATCH, &patterns(&["a()", "unlock"]), DiffContext::Lines(0));
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(texts(&hunks[0]), [" \ta();"]);
+        assert_eq!(texts(&hunks[1]), [" \tunlock(o);"]);
+        assert_eq!(hunks[1].lines[0].number, Some(15));
+    }
+
+    #[test]
+    fn shows_whole_hunks_or_nothing() {
+        let whole = hunks(PATCH, &patterns(&["z()"]), DiffContext::Hunks);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].function, Some("int other(void)"));
+        assert_eq!(texts(&whole[0]), [" \tx();", "-\ty();", "+\tz();"]);
+
+        assert!(hunks(PATCH, &patterns(&["kfree"]), DiffContext::Off).is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_the_first_change() {
+        let hunks = hunks(PATCH, &[], DiffContext::Lines(1));
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(
+            texts(&hunks[0]),
+            [" \tb();", "-\tkfree(o);", "+\tkfree_rcu(o);"]
+        );
+    }
+
+    #[test]
+    fn reads_excerpts_without_headers() {
+        let excerpt = "+\tkfree(o);\n...\n+\tkfree(p);";
+        let hunks = hunks(excerpt, &patterns(&["kfree"]), DiffContext::Hunks);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(texts(&hunks[0]), ["+\tkfree(o);", "+\tkfree(p);"]);
+        assert!(hunks[0].lines.iter().all(|line| line.number.is_none()));
+    }
+
+    #[test]
+    fn finds_the_matched_span() {
+        assert_eq!(matched_span("+\tkfree_rcu(o);", "kfree"), Some((2, 7)));
+        assert_eq!(matched_span("+\tkfree_rcu(o);", "kfree*(o)"), Some((2, 14)));
+        assert_eq!(matched_span("+\tkfree_rcu(o);", "kfree?rcu"), Some((2, 11)));
+        assert_eq!(matched_span("KFREE(x)", "kfree"), Some((0, 5)));
+        assert_eq!(matched_span("+\tkfree(o);", "vfree"), None);
+        assert_eq!(matched_span("+\tkfree(o);", "*"), None);
+    }
+}
@@ -5,9 +5,11 @@ use std::error::Error;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::config::AppConfig;
 use crate::repository::GithubRepository;
+use crate::store;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct History {
     repositories: HashMap<String, String>,
 }
@@ -48,13 +50,13 @@ impl History {
     pub fn save(&self) -> Result<(), Box<dyn Error>> {
         let config_path = Self::get_config_path()?;
         let contents = serde_yaml::to_string(self)?;
-        fs::write(config_path, contents)?;
+        store::write(config_path, contents)?;
         Ok(())
     }
 
     /// Verifies whether there is a record for a given repository
     pub fn has(&self, repo: &GithubRepository) -> bool {
-        return self.repositories.contains_key(repo.uri().as_str());
+        self.repositories.contains_key(repo.uri().as_str())
     }
 
     /// Update/add a record for a given repository
@@ -69,12 +71,48 @@ impl History {
             .cloned()
             .ok_or_else(|| format!("No record for the repository {}", repo.name()).into())
     }
-}
 
-impl Default for History {
-    fn default() -> Self {
-        Self {
-            repositories: Default::default(),
+    /// All the records, sorted by repository (owner/name)
+    pub fn entries(&self) -> Vec<(&str, &str)> {
+        let mut entries: Vec<(&str, &str)> = self
+            .repositories
+            .iter()
+            .map(|(uri, hash)| (uri.as_str(), hash.as_str()))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// Update/add the record of a repository by its owner/name
+    pub fn set(&mut self, uri: &str, hash: String) {
+        self.repositories.insert(uri.to_owned(), hash);
+    }
+
+    /// Removes the record of a repository, returning the hash it pointed to
+    pub fn remove(&mut self, uri: &str) -> Option<String> {
+        self.repositories.remove(uri)
+    }
+
+    /// Removes the records of repositories that are no longer part of the
+    /// notification config, and returns their owner/name
+    pub fn prune(&mut self, config: &AppConfig) -> Vec<String> {
+        let watched: Vec<String> = config
+            .notifications()
+            .iter()
+            .map(|notification| notification.repository().uri())
+            .collect();
+
+        let mut removed: Vec<String> = self
+            .repositories
+            .keys()
+            .filter(|uri| !watched.contains(uri))
+            .cloned()
+            .collect();
+        removed.sort();
+
+        for uri in &removed {
+            self.repositories.remove(uri);
         }
+        removed
     }
 }
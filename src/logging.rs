@@ -0,0 +1,113 @@
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+
+/// How often a new log file is started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+/// How the lines of the log files are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON document per line, spans included
+    Json,
+}
+
+/// The diagnostics written to log files, separate from what is shown in the
+/// terminal
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Directory of the log files, `logs` within the data directory by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    directory: Option<String>,
+    /// Filter of the recorded events, a level (e.g. `debug`) or a list of
+    /// `target=level` directives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rotation: Option<LogRotation>,
+    /// How many of the log files are kept, all of them when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_files: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<LogFormat>,
+}
+
+impl LoggingConfig {
+    const DEFAULT_LEVEL: &'static str = "info";
+    const LOGS_DIRNAME: &'static str = "logs";
+    const FILENAME_PREFIX: &'static str = "vulngrep";
+    const FILENAME_SUFFIX: &'static str = "log";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// The directory of the log files
+    pub fn directory(&self) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(directory) = &self.directory {
+            return Ok(PathBuf::from(directory));
+        }
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find data directory")?;
+        Ok(proj_dirs.data_dir().join(Self::LOGS_DIRNAME))
+    }
+
+    /// The filter of the recorded events, the `VULNGREP_LOG` environment
+    /// variable taking precedence over the config
+    fn filter(&self) -> Result<EnvFilter, Box<dyn Error>> {
+        let directives = std::env::var("VULNGREP_LOG")
+            .ok()
+            .or_else(|| self.level.clone())
+            .unwrap_or_else(|| Self::DEFAULT_LEVEL.to_owned());
+        EnvFilter::builder()
+            .parse(&directives)
+            .map_err(|err| format!("Invalid log level {}: {}", directives, err).into())
+    }
+}
+
+/// Routes the diagnostics to rotated log files. The returned guard flushes
+/// the lines that are still buffered when dropped, so it must be held until
+/// the end of the program
+pub fn init(config: &LoggingConfig) -> Result<WorkerGuard, Box<dyn Error>> {
+    let directory = config.directory()?;
+    fs::create_dir_all(&directory)?;
+
+    let rotation = match config.rotation.unwrap_or_default() {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    let mut appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(LoggingConfig::FILENAME_PREFIX)
+        .filename_suffix(LoggingConfig::FILENAME_SUFFIX);
+    if let Some(max_files) = config.max_files {
+        appender = appender.max_log_files(max_files);
+    }
+    let (writer, guard) = tracing_appender::non_blocking(appender.build(directory)?);
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(config.filter()?)
+        .with_writer(writer)
+        .with_ansi(false);
+    let result = match config.format.unwrap_or_default() {
+        LogFormat::Text => subscriber.try_init(),
+        LogFormat::Json => subscriber.json().try_init(),
+    };
+    result.map_err(|err| format!("Failed to set up logging: {}", err))?;
+    Ok(guard)
+}
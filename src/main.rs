@@ -1,36 +1,500 @@
-use crate::repository::GithubRepositoryError;
-use crate::terminal::TerminalDisplay;
+use crate::cache::CommitCache;
+use crate::diff::DiffContext;
+use crate::repository::{GithubRepository, GithubRepositoryError};
+use crate::search::{SearchFilter, SearchIndex};
+use crate::terminal::{TerminalDisplay, Verbosity};
+use crate::watcher::ScanRange;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
 use config::AppConfig;
-use open;
+use history::History;
+use matches::MatchStore;
+use output::OutputFormat;
+use report::ReportFormat;
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
+use std::process;
 use std::rc::Rc;
 
+mod cache;
 mod config;
+mod diff;
 mod history;
+mod logging;
+mod matcher;
+mod matches;
+mod metrics;
+mod notifier;
+mod output;
+mod report;
 mod repository;
+mod sarif;
+mod search;
+mod server;
+mod status;
+mod store;
 mod terminal;
+mod triage;
+mod tui;
 mod watcher;
 
+const USAGE: &str = "Usage: vulngrep [config]
+       vulngrep history list
+       vulngrep history reset <owner/repo>
+       vulngrep history set <owner/repo> <sha>
+       vulngrep history prune
+       vulngrep scan <owner/repo> --since <YYYY-MM-DD|30d>
+       vulngrep scan <owner/repo> --range <base>..<head>
+       vulngrep rescan [owner/repo] [--since <YYYY-MM-DD|30d>]
+       vulngrep search <query> [--repo <owner/repo>] [--path <glob>]
+                               [--since <YYYY-MM-DD|30d>] [--until <YYYY-MM-DD|30d>]
+       vulngrep export <sarif|markdown|html> [--repo <owner/repo>] [--out <file>]
+                               [--since <YYYY-MM-DD|30d>] [--until <YYYY-MM-DD|30d>]
+       vulngrep tui [--no-watch]
+       vulngrep serve [--listen <address>]
+
+Options:
+       --output <human|json|ndjson>  Write every event as a line of JSON instead of text
+       --diff <off|hunks|N>          Lines of the matching patches to show, N lines
+                                     of context around the matching ones by default (2)
+       --no-color                    Write plain text, without colours nor links
+       --quiet                       Only show matches, results, warnings and errors
+       --verbose                     Also show what goes on behind the scenes
+
+When the output isn't a terminal, every line is written with a timestamp and a level.";
+
+/// Verifies that the argument has the owner/name form
+fn parse_repository_uri(uri: &str) -> Result<&str, Box<dyn Error>> {
+    match uri.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(uri)
+        }
+        _ => Err(format!("Invalid repository {}, expected owner/name", uri).into()),
+    }
+}
+
+/// Verifies that the argument is a full (40 characters long) commit hash
+fn parse_commit_hash(hash: &str) -> Result<String, Box<dyn Error>> {
+    // the cursor is compared against full hashes, so abbreviated ones would never match
+    if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(hash.to_ascii_lowercase())
+    } else {
+        Err(format!(
+            "Invalid commit hash {}, expected the full 40 characters long SHA",
+            hash
+        )
+        .into())
+    }
+}
+
+/// Parses either a date (YYYY-MM-DD) or a duration relative to now (e.g. 30d)
+fn parse_since(since: &str) -> Result<DateTime<Utc>, Box<dyn Error>> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    let secs = config::parse_duration(since).map_err(|_| {
+        format!(
+            "Invalid date {}, expected YYYY-MM-DD or a duration such as 30d",
+            since
+        )
+    })?;
+    Ok(config::seconds_ago(secs).map_err(|_| format!("Date too far in the past: {}", since))?)
+}
+
+/// Parses the (exclusive) end of a date range, a date (YYYY-MM-DD) includes the whole day
+fn parse_until(until: &str) -> Result<DateTime<Utc>, Box<dyn Error>> {
+    let date = parse_since(until)?;
+    if NaiveDate::parse_from_str(until, "%Y-%m-%d").is_ok() {
+        return date
+            .checked_add_signed(TimeDelta::days(1))
+            .ok_or_else(|| format!("Invalid date {}", until).into());
+    }
+    Ok(date)
+}
+
+/// Positional arguments, and the values of `--flag value` pairs
+type ParsedArgs<'a> = (Vec<&'a str>, HashMap<&'a str, &'a str>);
+
+/// Splits the arguments into positional ones and `--flag value` pairs
+fn parse_flags<'a>(
+    args: &'a [String],
+    known_flags: &[&str],
+) -> Result<ParsedArgs<'a>, Box<dyn Error>> {
+    let mut positional = Vec::new();
+    let mut flags = HashMap::new();
+
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+        if !known_flags.contains(&arg) {
+            return Err(format!("Unknown option {}", arg).into());
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for option {}", arg))?;
+        flags.insert(arg, value);
+    }
+
+    Ok((positional, flags))
+}
+
+/// Takes a global `--option value` pair out of the arguments
+fn take_option(args: &mut Vec<String>, option: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(index) = args.iter().position(|arg| arg == option) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        return Err(format!("Missing value for option {}", option).into());
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value))
+}
+
+/// Takes a global `--flag` out of the arguments, tells whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let Some(index) = args.iter().position(|arg| arg == flag) else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// Builds the display out of the global options, taking them out of the arguments
+fn take_display_options(args: &mut Vec<String>) -> Result<TerminalDisplay, Box<dyn Error>> {
+    let output = take_option(args, "--output")?
+        .map(|output| output.parse::<OutputFormat>())
+        .transpose()?
+        .unwrap_or_default();
+    let diff = take_option(args, "--diff")?
+        .map(|diff| diff.parse::<DiffContext>())
+        .transpose()?
+        .unwrap_or_default();
+    let verbosity = match (take_flag(args, "--quiet"), take_flag(args, "--verbose")) {
+        (false, false) => Verbosity::Normal,
+        (true, false) => Verbosity::Quiet,
+        (false, true) => Verbosity::Verbose,
+        (true, true) => return Err("Options --quiet and --verbose can't be combined".into()),
+    };
+    if take_flag(args, "--no-color") {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    Ok(TerminalDisplay::with_output(output)
+        .with_diff(diff)
+        .with_verbosity(verbosity))
+}
+
+/// Parses a `base..head` commit range
+fn parse_range(range: &str) -> Result<ScanRange, Box<dyn Error>> {
+    match range.split_once("..") {
+        Some((base, head))
+            if base.len() >= 7
+                && base.chars().all(|c| c.is_ascii_hexdigit())
+                && !head.is_empty() =>
+        {
+            Ok(ScanRange::Between {
+                base: base.to_ascii_lowercase(),
+                head: head.to_owned(),
+            })
+        }
+        _ => Err(format!(
+            "Invalid range {}, expected <base>..<head> where base is a (possibly abbreviated) SHA",
+            range
+        )
+        .into()),
+    }
+}
+
+/// Handles the `scan` subcommand
+async fn scan_command(display: Rc<TerminalDisplay>, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (uri, range) = match args.as_slice() {
+        [uri, "--since", since] => (
+            parse_repository_uri(uri)?,
+            ScanRange::Since(parse_since(since)?),
+        ),
+        [uri, "--range", range] => (parse_repository_uri(uri)?, parse_range(range)?),
+        _ => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    let watcher = watcher::RepositoryWatcher::new(display, false)?;
+    watcher.scan(uri, range).await
+}
+
+/// Handles the `rescan` subcommand
+async fn rescan_command(
+    display: Rc<TerminalDisplay>,
+    args: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (uri, since) = match args.as_slice() {
+        [] => (None, None),
+        ["--since", since] => (None, Some(parse_since(since)?)),
+        [uri] => (Some(parse_repository_uri(uri)?), None),
+        [uri, "--since", since] => (Some(parse_repository_uri(uri)?), Some(parse_since(since)?)),
+        _ => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    let watcher = watcher::RepositoryWatcher::new(display, false)?;
+    watcher.rescan(uri, since).await
+}
+
+/// Handles the `tui` subcommand
+fn tui_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let watch = match args.as_slice() {
+        [] => true,
+        ["--no-watch"] => false,
+        _ => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+    tui::run(watch)
+}
+
+/// Handles the `serve` subcommand
+async fn serve_command(display: &TerminalDisplay, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (positional, flags) = parse_flags(args, &["--listen"])?;
+    if !positional.is_empty() {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let config = AppConfig::load()?;
+    let server = config.server();
+    let listen = flags.get("--listen").copied().unwrap_or(server.listen());
+    server::serve(display, listen).await
+}
+
+/// Handles the `search` subcommand
+fn search_command(display: &TerminalDisplay, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (positional, flags) = parse_flags(args, &["--repo", "--path", "--since", "--until"])?;
+    let [query] = positional.as_slice() else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+
+    let filter = SearchFilter {
+        repository: flags
+            .get("--repo")
+            .map(|uri| parse_repository_uri(uri).map(str::to_owned))
+            .transpose()?,
+        path: flags.get("--path").map(|path| path.to_string()),
+        since: flags
+            .get("--since")
+            .map(|since| parse_since(since))
+            .transpose()?,
+        until: flags
+            .get("--until")
+            .map(|until| parse_until(until))
+            .transpose()?,
+    };
+
+    let config = AppConfig::load()?;
+    let cache = CommitCache::open(&config.cache())?
+        .ok_or("The commit cache is disabled, there is nothing to search")?;
+
+    // index whatever has been cached since the last search
+    let mut index = SearchIndex::load()?;
+    if index.update(&cache)? {
+        index.save()?;
+    }
+
+    let hits = index.search(&cache, query, &filter)?;
+    for hit in &hits {
+        display.search_result(&hit.repository, &hit.html_url, &hit.sha, hit.date);
+        for line in &hit.message_lines {
+            display.search_line("message", line, query);
+        }
+        for (filename, lines) in &hit.files {
+            for line in lines {
+                display.search_line(filename, line, query);
+            }
+        }
+    }
+    display.search_summary(hits.len());
+
+    Ok(())
+}
+
+/// Handles the `export` subcommand
+fn export_command(display: &TerminalDisplay, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (positional, flags) = parse_flags(args, &["--repo", "--out", "--since", "--until"])?;
+    let [format] = positional.as_slice() else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+    let report_format = match *format {
+        "sarif" => None,
+        format => Some(format.parse::<ReportFormat>()?),
+    };
+    let repository = flags
+        .get("--repo")
+        .map(|uri| parse_repository_uri(uri))
+        .transpose()?;
+    let since = flags
+        .get("--since")
+        .map(|since| parse_since(since))
+        .transpose()?;
+    let until = flags
+        .get("--until")
+        .map(|until| parse_until(until))
+        .transpose()?;
+
+    // matches recorded before the date field existed have no commit date and
+    // are left out of date ranges
+    let in_range = |date: Option<DateTime<Utc>>| match (since, until) {
+        (None, None) => true,
+        _ => date.is_some_and(|date| {
+            since.is_none_or(|since| date >= since) && until.is_none_or(|until| date < until)
+        }),
+    };
+    let store = MatchStore::load()?;
+    let matches: Vec<_> = store
+        .matches()
+        .iter()
+        .filter(|commit_match| repository.is_none_or(|uri| commit_match.repository == uri))
+        .filter(|commit_match| in_range(commit_match.date))
+        .cloned()
+        .collect();
+
+    let contents = match report_format {
+        Some(format) => report::render(format, &report::title(since, until), &matches)?,
+        None => serde_json::to_string_pretty(&sarif::report(&matches))?,
+    };
+    match flags.get("--out") {
+        Some(path) => {
+            std::fs::write(path, contents)?;
+            display.display_success(&format!(
+                "Exported {} matching commits to {}",
+                matches.len(),
+                path
+            ));
+        }
+        None => println!("{}", contents),
+    }
+    Ok(())
+}
+
+/// Handles the `history` subcommands
+async fn history_command(display: &TerminalDisplay, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut history = History::load()?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["list"] => {
+            let entries = history.entries();
+            if entries.is_empty() {
+                display.display_warning("History is empty!");
+            }
+            for (uri, hash) in entries {
+                display.history_record(uri, hash);
+            }
+        }
+        ["reset", uri] => {
+            let uri = parse_repository_uri(uri)?;
+            match history.remove(uri) {
+                Some(_) => {
+                    history.save()?;
+                    display.display_success(&format!(
+                        "Repository {} has been reset, it will be re-added on the next run",
+                        uri
+                    ));
+                }
+                None => display.display_warning(&format!("No record for the repository {}", uri)),
+            }
+        }
+        ["set", uri, hash] => {
+            let uri = parse_repository_uri(uri)?;
+            let hash = parse_commit_hash(hash)?;
+            // the next run walks the history down to the commit, it has to be
+            // found on the default branch
+            let config = AppConfig::load()?;
+            let (owner, name) = uri.split_once('/').unwrap_or_default();
+            let repo = GithubRepository::new(owner, name, config.token()).await?;
+            let head_commit = repo.get_head().await?;
+            if !repo.is_ancestor(&hash, &head_commit.sha).await? {
+                return Err(
+                    format!("Commit {} is not on the default branch of {}", hash, uri).into(),
+                );
+            }
+            history.set(uri, hash.clone());
+            history.save()?;
+            display.display_success(&format!(
+                "Repository {} will be checked from commit {} on the next run",
+                uri, hash
+            ));
+        }
+        ["prune"] => {
+            let config = AppConfig::load()?;
+            let removed = history.prune(&config);
+            if removed.is_empty() {
+                display.display_success("Nothing to prune");
+                return Ok(());
+            }
+            history.save()?;
+            for uri in removed {
+                display.display_success(&format!("Repository {} has been pruned", uri));
+            }
+        }
+        _ => println!("{}", USAGE),
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     // parse command line arguments first
-    let args: Vec<String> = env::args().collect();
-    if args.len() == 2 {
-        let command = &args[1];
-        if command == "config" {
-            let config_path = AppConfig::get_config_path()?;
-            open::that(config_path.as_os_str())?;
-            return Ok(());
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let display = match take_display_options(&mut args) {
+        Ok(display) => Rc::new(display),
+        Err(e) => {
+            TerminalDisplay::new().display_error(e.to_string().as_str());
+            process::exit(1);
+        }
+    };
+
+    if let Some(command) = args.first() {
+        let result = match command.as_str() {
+            "config" if args.len() == 1 => {
+                let config_path = AppConfig::get_config_path()?;
+                open::that(config_path.as_os_str())?;
+                Ok(())
+            }
+            "history" => history_command(&display, &args[1..]).await,
+            "scan" => scan_command(display.clone(), &args[1..]).await,
+            "rescan" => rescan_command(display.clone(), &args[1..]).await,
+            "search" => search_command(&display, &args[1..]),
+            "export" => export_command(&display, &args[1..]),
+            "tui" => tui_command(&args[1..]),
+            "serve" => serve_command(&display, &args[1..]).await,
+            _ => {
+                println!("{}", USAGE);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            display.display_error(e.to_string().as_str());
+            // lets scripts tell that the command failed
+            process::exit(1);
         }
-        println!("Usage: vulngrep [config]");
         return Ok(());
     }
 
-    let display = Rc::new(TerminalDisplay::new());
-
     // kick off the watcher
-    let mut watcher = watcher::RepositoryWatcher::new(display.clone())?;
+    let mut watcher = watcher::RepositoryWatcher::new(display.clone(), true)?;
     match watcher.run().await {
         Ok(_) => (),
         Err(e)
@@ -44,3 +508,30 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        let since = parse_since("2026-10-01").unwrap();
+        assert_eq!(since.to_rfc3339(), "2026-10-01T00:00:00+00:00");
+        let until = parse_until("2026-10-01").unwrap();
+        assert_eq!(until.to_rfc3339(), "2026-10-02T00:00:00+00:00");
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let since = parse_since("1d").unwrap();
+        let expected = Utc::now() - TimeDelta::days(1);
+        assert!((expected - since).num_seconds().abs() < 5);
+    }
+
+    #[test]
+    fn rejects_dates_too_far_in_the_past() {
+        assert!(parse_since("200000000d").is_err());
+        assert!(parse_since("99999999999999999d").is_err());
+        assert!(parse_until("200000000d").is_err());
+    }
+}
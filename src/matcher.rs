@@ -0,0 +1,504 @@
+use crate::config::{Notification, Severity};
+use crate::diff;
+use chrono::{DateTime, Utc};
+use octocrab::models::repos::RepoCommit;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
+use wildmatch::WildMatch;
+
+/// A committed file that matched one of the notification rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMatch {
+    pub filename: String,
+    pub additions: u64,
+    pub deletions: u64,
+    /// Empty when the file matched by path alone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    /// The lines of the patch responsible for the match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+    /// Line numbers of the patch lines responsible for the match, in the new
+    /// version of the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<u64>,
+    /// The whole patch of the file, only kept while the match is delivered
+    #[serde(skip)]
+    pub patch: Option<String>,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Names of the notifiers the match goes to, all of them when unset
+    #[serde(skip)]
+    pub notify: Option<Vec<String>>,
+}
+
+/// A commit that matched one of the notification rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitMatch {
+    pub repository: String,
+    pub sha: String,
+    pub html_url: String,
+    /// The first line of the commit message
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// When the commit was authored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<DateTime<Utc>>,
+    /// The repository-wide pattern found in the commit message, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileMatch>,
+    /// The highest severity of the rules responsible for the match
+    #[serde(default)]
+    pub severity: Severity,
+    /// Names of the notifiers the match goes to, all of them when unset
+    #[serde(skip)]
+    pub notify: Option<Vec<String>>,
+}
+
+impl CommitMatch {
+    fn new(repository: &str, commit: &RepoCommit) -> Self {
+        Self {
+            repository: repository.to_owned(),
+            sha: commit.sha.clone(),
+            html_url: commit.html_url.clone(),
+            summary: commit
+                .commit
+                .message
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+            author: commit.author.as_ref().map(|author| author.login.clone()),
+            date: commit_date(commit),
+            message_pattern: None,
+            files: Vec::new(),
+            severity: Severity::default(),
+            notify: None,
+        }
+    }
+
+    /// The abbreviated hash, as shown by GitHub
+    pub fn short_sha(&self) -> &str {
+        self.sha.get(..7).unwrap_or(&self.sha)
+    }
+
+    /// Every pattern responsible for the match, without duplicates
+    pub fn patterns(&self) -> Vec<&str> {
+        let mut patterns: Vec<&str> = Vec::new();
+        let file_patterns = self.files.iter().flat_map(|file| &file.patterns);
+        for pattern in self.message_pattern.iter().chain(file_patterns) {
+            if !patterns.contains(&pattern.as_str()) {
+                patterns.push(pattern);
+            }
+        }
+        patterns
+    }
+
+    /// A copy of the match restricted to some of its files, or `None` when
+    /// none of them is left
+    pub fn filter_files(&self, predicate: impl Fn(&FileMatch) -> bool) -> Option<CommitMatch> {
+        let files: Vec<FileMatch> = self
+            .files
+            .iter()
+            .filter(|file| predicate(file))
+            .cloned()
+            .collect();
+        if files.is_empty() {
+            return None;
+        }
+
+        let mut commit_match = CommitMatch {
+            files,
+            ..self.clone()
+        };
+        commit_match.route_files();
+        Some(commit_match)
+    }
+
+    /// Routes the commit everywhere any of its files goes, at the most urgent
+    /// severity among them
+    fn route_files(&mut self) {
+        self.severity = self
+            .files
+            .iter()
+            .map(|file| file.severity)
+            .max()
+            .unwrap_or_default();
+        self.notify = self
+            .files
+            .iter()
+            .map(|file| file.notify.as_ref())
+            .collect::<Option<Vec<_>>>()
+            .map(|routes| {
+                let mut names: Vec<String> = routes.into_iter().flatten().cloned().collect();
+                names.sort();
+                names.dedup();
+                names
+            });
+    }
+
+    /// Whether both matches are about the same commit, files and patterns
+    pub fn is_same_match(&self, other: &CommitMatch) -> bool {
+        self.repository == other.repository
+            && self.sha == other.sha
+            && self.message_pattern == other.message_pattern
+            && self.files.len() == other.files.len()
+            && self
+                .files
+                .iter()
+                .zip(&other.files)
+                .all(|(file, other_file)| {
+                    file.filename == other_file.filename && file.patterns == other_file.patterns
+                })
+    }
+}
+
+/// When the commit was authored
+pub fn commit_date(commit: &RepoCommit) -> Option<DateTime<Utc>> {
+    commit.commit.author.as_ref().and_then(|author| author.date)
+}
+
+/// Creates a wildcard out of the string, and makes it inclusive
+pub fn make_pattern(pattern: impl Into<String>) -> WildMatch {
+    let inclusive_pattern = format!("*{}*", pattern.into());
+    WildMatch::new_case_insensitive(inclusive_pattern.as_str())
+}
+
+/// Turns a pattern into an identifier made of lowercase words separated by
+/// dashes, e.g. `refcount_add(*)` becomes `refcount_add`
+pub fn slug(pattern: &str) -> String {
+    let slug: String = pattern
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect();
+    let words: Vec<&str> = slug.split('-').filter(|word| !word.is_empty()).collect();
+    words.join("-")
+}
+
+/// Figures out if a commit is of interest. A commit is of interest if:
+/// 1) It has a matching repository-wide pattern
+/// 2) It has a matching file path and no patterns attached to it
+/// 3) It has a matching file path and at least one matching file pattern
+pub fn evaluate(
+    repository: &str,
+    notification: &Notification,
+    commit: &RepoCommit,
+) -> Option<CommitMatch> {
+    let mut commit_match = CommitMatch::new(repository, commit);
+
+    if let Some(patterns) = notification.patterns() {
+        if let Some(pattern) = patterns
+            .iter()
+            .find(|pattern| make_pattern(*pattern).matches(&commit.commit.message))
+        {
+            debug!(repository, sha = %commit.sha, pattern, "commit message matches");
+            commit_match.message_pattern = Some(pattern.clone());
+            commit_match.severity = notification.severity();
+            commit_match.notify = notification.notify().cloned();
+            return Some(commit_match);
+        }
+    }
+
+    let (Some(committed_files), Some(notification_files)) = (&commit.files, notification.files())
+    else {
+        debug!(repository, sha = %commit.sha, "commit has no files to match");
+        return None;
+    };
+
+    for committed_file in committed_files {
+        // the files of the notification are checked in order, up to the
+        // first one that doesn't match the path
+        for file in notification_files {
+            if !make_pattern(file.path()).matches(&committed_file.filename) {
+                trace!(
+                    repository,
+                    sha = %commit.sha,
+                    file = %committed_file.filename,
+                    path = file.path(),
+                    "file doesn't match the path"
+                );
+                break;
+            }
+
+            // Is there a matching file path with no patterns?
+            let mut patterns_responsible_for_hit: Vec<String> = Vec::new();
+            let mut is_file_of_interest = file.pattern().is_none();
+
+            // Is there a matching file-wide or repository-wide pattern?
+            if let (false, Some(patch)) = (is_file_of_interest, &committed_file.patch) {
+                let patterns_list = [file.pattern(), notification.patterns()];
+                for patterns in patterns_list.into_iter().flatten() {
+                    if let Some(pattern) = patterns
+                        .iter()
+                        .find(|pattern| make_pattern(*pattern).matches(patch))
+                    {
+                        patterns_responsible_for_hit.push(pattern.to_string());
+                        is_file_of_interest = true;
+                        break;
+                    }
+                }
+            }
+
+            match (is_file_of_interest, &committed_file.patch) {
+                (true, _) => debug!(
+                    repository,
+                    sha = %commit.sha,
+                    file = %committed_file.filename,
+                    path = file.path(),
+                    patterns = ?patterns_responsible_for_hit,
+                    "file matches"
+                ),
+                // GitHub leaves the patches of large files out
+                (false, None) => debug!(
+                    repository,
+                    sha = %commit.sha,
+                    file = %committed_file.filename,
+                    path = file.path(),
+                    "file has no patch to match the patterns against"
+                ),
+                (false, Some(_)) => debug!(
+                    repository,
+                    sha = %commit.sha,
+                    file = %committed_file.filename,
+                    path = file.path(),
+                    "no pattern matches the patch of the file"
+                ),
+            }
+
+            if is_file_of_interest {
+                let patch = committed_file.patch.as_deref();
+                let excerpt =
+                    patch.and_then(|patch| diff::excerpt(patch, &patterns_responsible_for_hit));
+                let lines = patch
+                    .map(|patch| diff::matching_lines(patch, &patterns_responsible_for_hit))
+                    .unwrap_or_default();
+                commit_match.files.push(FileMatch {
+                    filename: committed_file.filename.clone(),
+                    additions: committed_file.additions,
+                    deletions: committed_file.deletions,
+                    patterns: patterns_responsible_for_hit,
+                    excerpt,
+                    lines,
+                    patch: patch.map(str::to_owned),
+                    severity: file.severity().unwrap_or(notification.severity()),
+                    notify: file.notify().or(notification.notify()).cloned(),
+                });
+                break;
+            }
+        }
+    }
+
+    if commit_match.files.is_empty() {
+        debug!(repository, sha = %commit.sha, "commit doesn't match");
+        return None;
+    }
+
+    debug!(
+        repository,
+        sha = %commit.sha,
+        files = commit_match.files.len(),
+        "commit matches"
+    );
+    commit_match.route_files();
+    Some(commit_match)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const API_URL: &str = "https://api.github.com/repos/torvalds/linux";
+
+    /// A commit of torvalds/linux with the given message and files (name and patch)
+    pub fn repo_commit(sha: &str, message: &str, files: &[(&str, Option<&str>)]) -> RepoCommit {
+        let files: Vec<_> = files
+            .iter()
+            .map(|(filename, patch)| {
+                json!({
+                    "sha": sha,
+                    "filename": filename,
+                    "status": "modified",
+                    "additions": 1,
+                    "deletions": 0,
+                    "changes": 1,
+                    "blob_url": null,
+                    "raw_url": null,
+                    "contents_url": format!("{}/contents/{}", API_URL, filename),
+                    "patch": patch,
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "url": format!("{}/commits/{}", API_URL, sha),
+            "sha": sha,
+            "node_id": "C_1",
+            "html_url": format!("https://github.com/torvalds/linux/commit/{}", sha),
+            "comments_url": format!("{}/commits/{}/comments", API_URL, sha),
+            "commit": {
+                "url": format!("{}/git/commits/{}", API_URL, sha),
+                "author": {
+                    "name": "Alice",
+                    "email": "alice@example.com",
+                    "date": "2026-10-01T12:00:00Z",
+                },
+                "committer": null,
+                "message": message,
+                "comment_count": 0,
+                "tree": { "sha": sha, "url": format!("{}/git/trees/{}", API_URL, sha) },
+            },
+            "author": null,
+            "committer": null,
+            "parents": [],
+            "files": files,
+        }))
+        .unwrap()
+    }
+
+    /// A notification rule parsed from its YAML configuration
+    pub fn notification(yaml: &str) -> Notification {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const PATCH: &str = "@@ -10,2 +10,3 @@\n \tlock(obj);\n+\trefcount_add(1, &obj->ref);\n";
+
+    #[test]
+    fn matches_a_path_without_patterns() {
+        let notification = notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             files: [{ path: fs/btrfs/ }]",
+        );
+        let commit = repo_commit("a1", "btrfs: cleanup", &[("fs/btrfs/sysfs.c", Some(PATCH))]);
+
+        let commit_match = evaluate("torvalds/linux", &notification, &commit).unwrap();
+        assert_eq!(commit_match.files.len(), 1);
+        assert_eq!(commit_match.files[0].filename, "fs/btrfs/sysfs.c");
+        assert!(commit_match.files[0].patterns.is_empty());
+        assert_eq!(commit_match.summary, "btrfs: cleanup");
+    }
+
+    #[test]
+    fn matches_a_pattern_of_the_patch() {
+        let notification = notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             files: [{ path: drivers/phy/, pattern: [\"refcount_add(*)\"], severity: critical }]",
+        );
+        let commit = repo_commit(
+            "b2",
+            "phy: fix refcount",
+            &[
+                ("drivers/phy/phy-core.c", Some(PATCH)),
+                (
+                    "drivers/phy/phy-other.c",
+                    Some("@@ -1 +1 @@\n+\treturn 0;\n"),
+                ),
+            ],
+        );
+
+        let commit_match = evaluate("torvalds/linux", &notification, &commit).unwrap();
+        assert_eq!(commit_match.files.len(), 1);
+        let file = &commit_match.files[0];
+        assert_eq!(file.filename, "drivers/phy/phy-core.c");
+        assert_eq!(file.patterns, ["refcount_add(*)"]);
+        assert_eq!(file.lines, [11]);
+        assert_eq!(commit_match.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn matches_a_pattern_of_the_message() {
+        let notification = notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             pattern: [UAF]\n\
+             files: [{ path: drivers/, pattern: [nothing] }]",
+        );
+        let commit = repo_commit(
+            "c3",
+            "mm: fix a UAF in the slab",
+            &[("mm/slab.c", Some(PATCH))],
+        );
+
+        let commit_match = evaluate("torvalds/linux", &notification, &commit).unwrap();
+        assert_eq!(commit_match.message_pattern.as_deref(), Some("UAF"));
+        assert!(commit_match.files.is_empty());
+    }
+
+    #[test]
+    fn ignores_commits_that_match_nothing() {
+        let notification = notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             pattern: [UAF]\n\
+             files: [{ path: drivers/phy/, pattern: [\"refcount_dec(*)\"] }]",
+        );
+        let commit = repo_commit(
+            "d4",
+            "phy: cleanup",
+            &[
+                ("drivers/phy/phy-core.c", Some(PATCH)),
+                ("fs/btrfs/sysfs.c", Some(PATCH)),
+            ],
+        );
+        assert!(evaluate("torvalds/linux", &notification, &commit).is_none());
+
+        // a file without patch can't match the patterns
+        let commit = repo_commit("e5", "phy: cleanup", &[("drivers/phy/phy-core.c", None)]);
+        assert!(evaluate("torvalds/linux", &notification, &commit).is_none());
+    }
+
+    #[test]
+    fn stops_at_the_first_path_that_does_not_match() {
+        let notification = notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             files:\n\
+             - { path: drivers/, pattern: [\"refcount_dec(*)\"] }\n\
+             - { path: drivers/phy/ }\n\
+             - { path: fs/btrfs/ }",
+        );
+
+        // the first path matches but not its pattern, the next path is checked
+        let commit = repo_commit(
+            "f6",
+            "phy: cleanup",
+            &[("drivers/phy/phy-core.c", Some(PATCH))],
+        );
+        let commit_match = evaluate("torvalds/linux", &notification, &commit).unwrap();
+        assert_eq!(commit_match.files[0].filename, "drivers/phy/phy-core.c");
+
+        // the first path doesn't match, the later ones aren't checked
+        let commit = repo_commit("a7", "btrfs: cleanup", &[("fs/btrfs/sysfs.c", Some(PATCH))]);
+        assert!(evaluate("torvalds/linux", &notification, &commit).is_none());
+    }
+
+    /// A match of the `UAF` pattern in a single file of torvalds/linux
+    pub fn commit_match(sha: &str, filename: &str) -> CommitMatch {
+        let file = FileMatch {
+            filename: filename.to_owned(),
+            additions: 1,
+            deletions: 0,
+            patterns: vec!["UAF".to_owned()],
+            excerpt: Some("+\tkfree(obj); /* UAF */".to_owned()),
+            lines: vec![11],
+            patch: Some("@@ -10,2 +10,3 @@\n \tlock(obj);\n+\tkfree(obj); /* UAF */\n".to_owned()),
+            severity: Severity::High,
+            notify: None,
+        };
+        CommitMatch {
+            repository: "torvalds/linux".to_owned(),
+            sha: sha.to_owned(),
+            html_url: format!("https://github.com/torvalds/linux/commit/{}", sha),
+            summary: "mm: fix a use after free".to_owned(),
+            author: Some("alice".to_owned()),
+            date: None,
+            message_pattern: None,
+            files: vec![file],
+            severity: Severity::High,
+            notify: None,
+        }
+    }
+}
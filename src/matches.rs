@@ -0,0 +1,129 @@
+use crate::matcher::CommitMatch;
+use crate::store;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Every match that has been reported to the user so far
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MatchStore {
+    matches: Vec<CommitMatch>,
+    /// Positions of the matches of every commit, keyed by repository and hash
+    #[serde(skip)]
+    index: HashMap<(String, String), Vec<usize>>,
+    /// Whether matches have been recorded since the store was last saved
+    #[serde(skip)]
+    unsaved: bool,
+}
+
+impl MatchStore {
+    const CONFIG_FILENAME: &'static str = "matches.yaml";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the store file path based on the operating system
+    pub fn get_config_path() -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find config directory")?;
+
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir)?;
+
+        Ok(data_dir.join(Self::CONFIG_FILENAME))
+    }
+
+    /// Load the matches from the default location
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(config_path)?;
+        let mut store: MatchStore = serde_yaml::from_str(&contents)?;
+        for (position, commit_match) in store.matches.iter().enumerate() {
+            store
+                .index
+                .entry(Self::key(commit_match))
+                .or_default()
+                .push(position);
+        }
+        Ok(store)
+    }
+
+    /// Save the matches to the default location
+    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+        let contents = serde_yaml::to_string(self)?;
+        store::write(config_path, contents)?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Saves the matches recorded since the last time, if any
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.unsaved {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn key(commit_match: &CommitMatch) -> (String, String) {
+        (commit_match.repository.clone(), commit_match.sha.clone())
+    }
+
+    /// Every recorded match, oldest first
+    pub fn matches(&self) -> &[CommitMatch] {
+        &self.matches
+    }
+
+    /// Verifies whether the exact same match (commit, files and patterns) has
+    /// been recorded before
+    pub fn contains(&self, commit_match: &CommitMatch) -> bool {
+        self.index
+            .get(&Self::key(commit_match))
+            .is_some_and(|positions| {
+                positions
+                    .iter()
+                    .any(|position| self.matches[*position].is_same_match(commit_match))
+            })
+    }
+
+    /// Records a match, returns false if it had already been recorded. It's
+    /// only written to disk by the next flush
+    pub fn record(&mut self, commit_match: CommitMatch) -> bool {
+        if self.contains(&commit_match) {
+            return false;
+        }
+        self.index
+            .entry(Self::key(&commit_match))
+            .or_default()
+            .push(self.matches.len());
+        self.matches.push(commit_match);
+        self.unsaved = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+
+    #[test]
+    fn records_each_match_once() {
+        let mut store = MatchStore::default();
+        assert!(store.record(commit_match("abc", "mm/slab.c")));
+        assert!(!store.record(commit_match("abc", "mm/slab.c")));
+        // another file of the same commit is another match
+        assert!(store.record(commit_match("abc", "mm/slub.c")));
+        assert!(store.contains(&commit_match("abc", "mm/slub.c")));
+        assert!(!store.contains(&commit_match("def", "mm/slab.c")));
+        assert_eq!(store.matches().len(), 2);
+    }
+}
@@ -0,0 +1,478 @@
+use crate::status::StatusStore;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use octocrab::models::Rate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Upper bounds of the buckets of the cycle duration histogram, in seconds
+const CYCLE_BUCKETS: [f64; 9] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Address the metrics are served on, `127.0.0.1:9898` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    listen: Option<String>,
+}
+
+impl MetricsConfig {
+    const DEFAULT_LISTEN: &'static str = "127.0.0.1:9898";
+
+    pub fn listen(&self) -> &str {
+        self.listen.as_deref().unwrap_or(Self::DEFAULT_LISTEN)
+    }
+}
+
+/// Everything counted since the start of the watcher
+struct Registry {
+    cycles: u64,
+    /// Cycles per bucket of `CYCLE_BUCKETS`, the last one being `+Inf`
+    cycle_buckets: [u64; CYCLE_BUCKETS.len() + 1],
+    cycle_seconds: f64,
+    /// Keyed by endpoint and whether the request succeeded
+    requests: BTreeMap<(&'static str, bool), u64>,
+    rate_limit: Option<Rate>,
+    /// Keyed by repository
+    commits: BTreeMap<String, u64>,
+    /// Keyed by repository and rule
+    matches: BTreeMap<(String, String), u64>,
+    /// Keyed by sink
+    sink_failures: BTreeMap<String, u64>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    cycles: 0,
+    cycle_buckets: [0; CYCLE_BUCKETS.len() + 1],
+    cycle_seconds: 0.0,
+    requests: BTreeMap::new(),
+    rate_limit: None,
+    commits: BTreeMap::new(),
+    matches: BTreeMap::new(),
+    sink_failures: BTreeMap::new(),
+});
+
+/// Updates the registry, counting is best effort
+fn update(update: impl FnOnce(&mut Registry)) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        update(&mut registry);
+    }
+}
+
+pub fn cycle(duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    let bucket = CYCLE_BUCKETS
+        .iter()
+        .position(|bound| seconds <= *bound)
+        .unwrap_or(CYCLE_BUCKETS.len());
+    update(|registry| {
+        registry.cycles += 1;
+        registry.cycle_buckets[bucket] += 1;
+        registry.cycle_seconds += seconds;
+    });
+}
+
+/// Counts a request to the GitHub API
+pub fn request(endpoint: &'static str, succeeded: bool) {
+    update(|registry| *registry.requests.entry((endpoint, succeeded)).or_default() += 1);
+}
+
+pub fn rate_limit(rate: Rate) {
+    update(|registry| registry.rate_limit = Some(rate));
+}
+
+pub fn commits_evaluated(repository: &str, commits: usize) {
+    update(|registry| {
+        *registry.commits.entry(repository.to_owned()).or_default() += commits as u64;
+    });
+}
+
+/// Counts a reported match, once for every rule responsible for it
+pub fn matched(repository: &str, rules: &[&str]) {
+    update(|registry| {
+        for rule in rules {
+            let key = (repository.to_owned(), (*rule).to_owned());
+            *registry.matches.entry(key).or_default() += 1;
+        }
+    });
+}
+
+pub fn sink_failure(sink: &str) {
+    update(|registry| *registry.sink_failures.entry(sink.to_owned()).or_default() += 1);
+}
+
+/// Escapes a label value of the text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes the header of a metric
+fn describe(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// Renders the metrics in the Prometheus text exposition format
+fn render(registry: &Registry, status: &StatusStore) -> String {
+    let mut text = String::new();
+
+    describe(
+        &mut text,
+        "vulngrep_cycles_total",
+        "counter",
+        "Cycles run by the watcher.",
+    );
+    let _ = writeln!(text, "vulngrep_cycles_total {}", registry.cycles);
+
+    describe(
+        &mut text,
+        "vulngrep_cycle_duration_seconds",
+        "histogram",
+        "How long the cycles of the watcher took.",
+    );
+    let mut cumulative = 0;
+    for (index, count) in registry.cycle_buckets.iter().enumerate() {
+        cumulative += count;
+        let bound = CYCLE_BUCKETS
+            .get(index)
+            .map(f64::to_string)
+            .unwrap_or_else(|| "+Inf".to_owned());
+        let _ = writeln!(
+            text,
+            "vulngrep_cycle_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, cumulative
+        );
+    }
+    let _ = writeln!(
+        text,
+        "vulngrep_cycle_duration_seconds_sum {}",
+        registry.cycle_seconds
+    );
+    let _ = writeln!(
+        text,
+        "vulngrep_cycle_duration_seconds_count {}",
+        registry.cycles
+    );
+
+    describe(
+        &mut text,
+        "vulngrep_github_requests_total",
+        "counter",
+        "Requests to the GitHub API.",
+    );
+    for ((endpoint, succeeded), count) in &registry.requests {
+        let outcome = if *succeeded { "ok" } else { "error" };
+        let _ = writeln!(
+            text,
+            "vulngrep_github_requests_total{{endpoint=\"{}\",outcome=\"{}\"}} {}",
+            endpoint, outcome, count
+        );
+    }
+
+    if let Some(rate) = &registry.rate_limit {
+        describe(
+            &mut text,
+            "vulngrep_github_rate_limit_remaining",
+            "gauge",
+            "Requests left to the GitHub API until the rate limit resets.",
+        );
+        let _ = writeln!(
+            text,
+            "vulngrep_github_rate_limit_remaining {}",
+            rate.remaining
+        );
+        describe(
+            &mut text,
+            "vulngrep_github_rate_limit",
+            "gauge",
+            "Requests allowed to the GitHub API per hour.",
+        );
+        let _ = writeln!(text, "vulngrep_github_rate_limit {}", rate.limit);
+        describe(
+            &mut text,
+            "vulngrep_github_rate_limit_reset_timestamp_seconds",
+            "gauge",
+            "When the rate limit of the GitHub API resets.",
+        );
+        let _ = writeln!(
+            text,
+            "vulngrep_github_rate_limit_reset_timestamp_seconds {}",
+            rate.reset
+        );
+    }
+
+    describe(
+        &mut text,
+        "vulngrep_commits_evaluated_total",
+        "counter",
+        "Commits evaluated against the rules.",
+    );
+    for (repository, count) in &registry.commits {
+        let _ = writeln!(
+            text,
+            "vulngrep_commits_evaluated_total{{repository=\"{}\"}} {}",
+            escape(repository),
+            count
+        );
+    }
+
+    describe(
+        &mut text,
+        "vulngrep_matches_total",
+        "counter",
+        "Matches reported, per rule responsible for them.",
+    );
+    for ((repository, rule), count) in &registry.matches {
+        let _ = writeln!(
+            text,
+            "vulngrep_matches_total{{repository=\"{}\",rule=\"{}\"}} {}",
+            escape(repository),
+            escape(rule),
+            count
+        );
+    }
+
+    describe(
+        &mut text,
+        "vulngrep_sink_failures_total",
+        "counter",
+        "Failed deliveries of the notification sinks.",
+    );
+    for (sink, count) in &registry.sink_failures {
+        let _ = writeln!(
+            text,
+            "vulngrep_sink_failures_total{{sink=\"{}\"}} {}",
+            escape(sink),
+            count
+        );
+    }
+
+    describe(
+        &mut text,
+        "vulngrep_last_check_timestamp_seconds",
+        "gauge",
+        "When the repository was last checked.",
+    );
+    for (repository, status) in status.entries() {
+        let _ = writeln!(
+            text,
+            "vulngrep_last_check_timestamp_seconds{{repository=\"{}\"}} {}",
+            escape(repository),
+            status.checked.timestamp()
+        );
+    }
+    describe(
+        &mut text,
+        "vulngrep_last_success_timestamp_seconds",
+        "gauge",
+        "When the repository was last checked without errors.",
+    );
+    for (repository, status) in status.entries() {
+        if let Some(succeeded) = status.succeeded {
+            let _ = writeln!(
+                text,
+                "vulngrep_last_success_timestamp_seconds{{repository=\"{}\"}} {}",
+                escape(repository),
+                succeeded.timestamp()
+            );
+        }
+    }
+
+    text
+}
+
+async fn metrics() -> impl IntoResponse {
+    // the status is kept on disk by the watcher, it may be missing at first
+    let status = StatusStore::load().unwrap_or_default();
+    let text = match REGISTRY.lock() {
+        Ok(registry) => render(&registry, &status),
+        Err(_) => String::new(),
+    };
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        text,
+    )
+}
+
+/// Serves the metrics on `/metrics` until the program ends
+pub async fn serve(config: &MetricsConfig) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(config.listen()).await.map_err(|err| {
+        format!(
+            "Failed to serve the metrics on {}: {}",
+            config.listen(),
+            err
+        )
+    })?;
+    let app = Router::new().route("/metrics", get(metrics));
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> Registry {
+        Registry {
+            cycles: 0,
+            cycle_buckets: [0; CYCLE_BUCKETS.len() + 1],
+            cycle_seconds: 0.0,
+            requests: BTreeMap::new(),
+            rate_limit: None,
+            commits: BTreeMap::new(),
+            matches: BTreeMap::new(),
+            sink_failures: BTreeMap::new(),
+        }
+    }
+
+    /// The value of a sample, e.g. `vulngrep_cycles_total`
+    fn sample<'a>(text: &'a str, series: &str) -> Option<&'a str> {
+        text.lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+    }
+
+    #[test]
+    fn renders_the_exposition_format() {
+        let mut registry = registry();
+        registry.cycles = 3;
+        registry.cycle_buckets[0] = 1;
+        registry.cycle_buckets[2] = 1;
+        registry.cycle_buckets[CYCLE_BUCKETS.len()] = 1;
+        registry.cycle_seconds = 3608.5;
+        registry.requests.insert(("commits", true), 7);
+        registry.requests.insert(("commits", false), 1);
+        registry.rate_limit = Some(Rate {
+            limit: 5000,
+            used: 10,
+            remaining: 4990,
+            reset: 1_700_000_000,
+        });
+        registry.commits.insert("torvalds/linux".to_owned(), 42);
+        registry.matches.insert(
+            ("torvalds/linux".to_owned(), "refcount_add(*)".to_owned()),
+            2,
+        );
+        registry.sink_failures.insert("slack".to_owned(), 1);
+        let mut status = StatusStore::default();
+        status.record("torvalds/linux", Ok(()));
+        status.record("rust-lang/rust", Err("Not Found".to_owned()));
+
+        let text = render(&registry, &status);
+        assert_eq!(sample(&text, "vulngrep_cycles_total"), Some("3"));
+        // the buckets are cumulative
+        assert_eq!(
+            sample(&text, "vulngrep_cycle_duration_seconds_bucket{le=\"1\"}"),
+            Some("1")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_cycle_duration_seconds_bucket{le=\"10\"}"),
+            Some("2")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_cycle_duration_seconds_bucket{le=\"1800\"}"),
+            Some("2")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_cycle_duration_seconds_bucket{le=\"+Inf\"}"),
+            Some("3")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_cycle_duration_seconds_sum"),
+            Some("3608.5")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_cycle_duration_seconds_count"),
+            Some("3")
+        );
+        assert_eq!(
+            sample(
+                &text,
+                "vulngrep_github_requests_total{endpoint=\"commits\",outcome=\"error\"}"
+            ),
+            Some("1")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_github_rate_limit_remaining"),
+            Some("4990")
+        );
+        assert_eq!(
+            sample(
+                &text,
+                "vulngrep_matches_total{repository=\"torvalds/linux\",rule=\"refcount_add(*)\"}"
+            ),
+            Some("2")
+        );
+        assert_eq!(
+            sample(&text, "vulngrep_sink_failures_total{sink=\"slack\"}"),
+            Some("1")
+        );
+        assert!(sample(
+            &text,
+            "vulngrep_last_success_timestamp_seconds{repository=\"torvalds/linux\"}"
+        )
+        .is_some());
+        assert!(sample(
+            &text,
+            "vulngrep_last_success_timestamp_seconds{repository=\"rust-lang/rust\"}"
+        )
+        .is_none());
+        assert!(sample(
+            &text,
+            "vulngrep_last_check_timestamp_seconds{repository=\"rust-lang/rust\"}"
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn describes_every_metric_before_its_samples() {
+        let mut registry = registry();
+        registry.commits.insert("torvalds/linux".to_owned(), 1);
+        let text = render(&registry, &StatusStore::default());
+
+        let mut described: Vec<&str> = Vec::new();
+        for line in text.lines() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                described.push(help.split(' ').next().unwrap());
+                continue;
+            }
+            if line.starts_with("# TYPE ") {
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            let name = series.split('{').next().unwrap();
+            assert!(
+                described.iter().any(|metric| name.starts_with(metric)),
+                "{} is not described",
+                name
+            );
+            assert!(value.parse::<f64>().is_ok(), "{} is not a number", value);
+        }
+        // the rate limit is only known once the API has been called
+        assert!(!text.contains("vulngrep_github_rate_limit"));
+    }
+
+    #[test]
+    fn escapes_the_label_values() {
+        let mut registry = registry();
+        registry.matches.insert(
+            ("torvalds/linux".to_owned(), "say \"hi\"\\\n".to_owned()),
+            1,
+        );
+        let text = render(&registry, &StatusStore::default());
+        assert!(text.contains(r#"rule="say \"hi\"\\\n"} 1"#));
+    }
+}
@@ -0,0 +1,592 @@
+use super::template::{Escape, Template};
+use super::Suppressed;
+use crate::matcher::CommitMatch;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Body format of a webhook request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatFormat {
+    /// The documented vulngrep payload
+    #[default]
+    Json,
+    /// Slack incoming webhooks (Block Kit)
+    Slack,
+    /// Discord webhooks (embeds)
+    Discord,
+    /// Matrix, through the generic webhooks of matrix-hookshot
+    Matrix,
+    /// Mattermost incoming webhooks
+    Mattermost,
+}
+
+const USERNAME: &str = "vulngrep";
+/// Keeps code blocks well within the message size limits of every platform
+const MAX_EXCERPT_CHARS: usize = 700;
+/// Embed color used for Discord, orange
+const DISCORD_COLOR: u32 = 0xE67E22;
+/// Limits of the Discord embeds, anything larger gets the message rejected
+const DISCORD_MAX_FIELDS: usize = 25;
+const DISCORD_MAX_FIELD_NAME_CHARS: usize = 256;
+const DISCORD_MAX_FIELD_VALUE_CHARS: usize = 1024;
+/// Title, description and fields (names and values) of an embed, altogether
+const DISCORD_MAX_EMBED_CHARS: usize = 6000;
+/// Limit of the Mattermost posts
+const MATTERMOST_MAX_POST_CHARS: usize = 16383;
+/// Limits of the Slack Block Kit messages
+const SLACK_MAX_BLOCKS: usize = 50;
+const SLACK_MAX_SECTION_CHARS: usize = 3000;
+
+/// Renders a match into the incoming-webhook schema of a chat platform
+pub fn render(format: ChatFormat, commit_match: &CommitMatch) -> Value {
+    match format {
+        ChatFormat::Json => json!(super::payload::MatchPayload::from(commit_match)),
+        ChatFormat::Slack => slack(commit_match),
+        ChatFormat::Discord => discord(commit_match),
+        ChatFormat::Matrix => json!({
+            "username": USERNAME,
+            "text": markdown(commit_match),
+            "html": html(commit_match),
+        }),
+        ChatFormat::Mattermost => json!({
+            "username": USERNAME,
+            "text": markdown(commit_match),
+        }),
+    }
+}
+
+/// Renders the matches dropped because of the rate limits
+pub fn render_suppressed(format: ChatFormat, suppressed: &Suppressed) -> Value {
+    match format {
+        ChatFormat::Json => json!({
+            "event": "suppressed",
+            "count": suppressed.count,
+            "repositories": suppressed.repositories,
+        }),
+        format => render_text(format, &suppressed.to_string()),
+    }
+}
+
+/// Renders the body template of a notifier, with the values escaped for the
+/// markup of the chat platform. `None` when there is no body template
+pub fn render_template(
+    format: ChatFormat,
+    template: &Template,
+    commit_match: &CommitMatch,
+) -> Result<Option<Value>, Box<dyn Error>> {
+    let message = match format {
+        ChatFormat::Json => None,
+        ChatFormat::Slack => template
+            .escaped_body(commit_match, Escape::Slack)?
+            .map(|text| json!({ "text": text })),
+        ChatFormat::Discord => template
+            .escaped_body(commit_match, Escape::Markdown)?
+            .map(|text| json!({ "username": USERNAME, "content": text })),
+        ChatFormat::Matrix => {
+            let text = template.escaped_body(commit_match, Escape::Markdown)?;
+            let html = template.escaped_body(commit_match, Escape::Html)?;
+            text.zip(html).map(|(text, html)| {
+                json!({
+                    "username": USERNAME,
+                    "text": text,
+                    "html": html.replace('\n', "<br>"),
+                })
+            })
+        }
+        ChatFormat::Mattermost => template
+            .escaped_body(commit_match, Escape::Markdown)?
+            .map(|text| json!({ "username": USERNAME, "text": text })),
+    };
+    Ok(message)
+}
+
+/// Renders a plain message, escaped as a whole
+pub fn render_text(format: ChatFormat, text: &str) -> Value {
+    match format {
+        ChatFormat::Json => json!({ "event": "text", "text": text }),
+        ChatFormat::Slack => json!({ "text": slack_escape(text) }),
+        ChatFormat::Discord => json!({ "username": USERNAME, "content": text }),
+        ChatFormat::Matrix => json!({
+            "username": USERNAME,
+            "text": text,
+            "html": html_escape(text).replace('\n', "<br>"),
+        }),
+        ChatFormat::Mattermost => json!({ "username": USERNAME, "text": text }),
+    }
+}
+
+fn repository_url(commit_match: &CommitMatch) -> String {
+    format!("https://github.com/{}", commit_match.repository)
+}
+
+/// Cuts an excerpt down to size, and makes sure it can't close the code block
+pub fn code_excerpt(excerpt: &str) -> String {
+    code_excerpt_within(excerpt, MAX_EXCERPT_CHARS)
+}
+
+/// Same as [`code_excerpt`], with room for at most `max_chars` characters
+fn code_excerpt_within(excerpt: &str, max_chars: usize) -> String {
+    truncate(
+        &guard_fences(excerpt),
+        max_chars.min(MAX_EXCERPT_CHARS),
+        "\n...",
+    )
+}
+
+/// Breaks up the runs of backticks that would close a code block
+pub fn guard_fences(text: &str) -> String {
+    let mut text = text.to_owned();
+    // a longer run is left with three backticks after a pass
+    while text.contains("```") {
+        text = text.replace("```", "`\u{200b}``");
+    }
+    text
+}
+
+/// Cuts the text down to `max_chars` characters, ellipsis included
+fn truncate(text: &str, max_chars: usize, ellipsis: &str) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+    let kept = max_chars.saturating_sub(ellipsis.chars().count());
+    let mut text: String = text.chars().take(kept).collect();
+    text.push_str(ellipsis);
+    text
+}
+
+/// Room left for a code block within a text of `max_chars` characters that
+/// already holds `text`, once the fences and ellipsis are accounted for
+fn excerpt_budget(text: &str, fences: &str, max_chars: usize) -> Option<usize> {
+    let budget = max_chars.checked_sub(text.chars().count() + fences.chars().count())?;
+    // an excerpt of a few characters isn't worth showing
+    (budget >= 40).then_some(budget)
+}
+
+fn more_files(count: usize) -> String {
+    match count {
+        1 => "...and 1 more file".to_owned(),
+        count => format!("...and {} more files", count),
+    }
+}
+
+fn patterns_list(patterns: &[String]) -> String {
+    patterns
+        .iter()
+        .map(|pattern| format!("`{}`", pattern.replace('`', "'")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Escapes the characters that Slack's mrkdwn treats as control characters
+pub fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn html_escape(text: &str) -> String {
+    slack_escape(text).replace('"', "&quot;")
+}
+
+/// Escapes the characters that Markdown (Discord, Mattermost and Matrix) would
+/// otherwise turn into formatting, links or mentions
+pub fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '<' | '#' | '[' | ']' | '(' | ')' | '@'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn slack(commit_match: &CommitMatch) -> Value {
+    let author = commit_match.author.as_deref().unwrap_or("unknown");
+    let section = |text: String| {
+        json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": truncate(&text, SLACK_MAX_SECTION_CHARS, "..."),
+            },
+        })
+    };
+    let mut blocks = vec![section(format!(
+        "*New matching commit in <{}|{}>*\n<{}|`{}`> by {}",
+        repository_url(commit_match),
+        commit_match.repository,
+        commit_match.html_url,
+        commit_match.short_sha(),
+        slack_escape(author),
+    ))];
+
+    if let Some(pattern) = &commit_match.message_pattern {
+        blocks.push(section(format!(
+            "*Message:* {}\n*Pattern matched:* {}",
+            slack_escape(&commit_match.summary),
+            slack_escape(&patterns_list(std::slice::from_ref(pattern))),
+        )));
+    }
+
+    // the last block is kept to tell how many files didn't fit
+    let room = SLACK_MAX_BLOCKS - blocks.len();
+    let shown = if commit_match.files.len() > room {
+        room - 1
+    } else {
+        room
+    };
+    for file in commit_match.files.iter().take(shown) {
+        let mut text = format!(
+            "*{}*  `+{}` `-{}`",
+            slack_escape(&file.filename),
+            file.additions,
+            file.deletions
+        );
+        if !file.patterns.is_empty() {
+            text.push_str(&format!(
+                "\n*Patterns matched:* {}",
+                slack_escape(&patterns_list(&file.patterns))
+            ));
+        }
+        if let Some(excerpt) = &file.excerpt {
+            if let Some(budget) = excerpt_budget(&text, "\n``````", SLACK_MAX_SECTION_CHARS) {
+                // escaping makes the excerpt longer, so it's cut beforehand
+                let excerpt = slack_escape(&code_excerpt_within(excerpt, budget / 5));
+                text.push_str(&format!("\n```{}```", excerpt));
+            }
+        }
+        blocks.push(section(text));
+    }
+    if commit_match.files.len() > shown {
+        blocks.push(section(more_files(commit_match.files.len() - shown)));
+    }
+
+    json!({
+        // shown in notifications, where blocks aren't rendered
+        "text": format!("New matching commit in {}", commit_match.repository),
+        "blocks": blocks,
+    })
+}
+
+fn discord(commit_match: &CommitMatch) -> Value {
+    let author = commit_match.author.as_deref().unwrap_or("unknown");
+    let title = format!("New matching commit in {}", commit_match.repository);
+    let description = format!(
+        "[`{}`]({}) by {}",
+        commit_match.short_sha(),
+        commit_match.html_url,
+        markdown_escape(author)
+    );
+    let field = |name: &str, value: String| {
+        let name = truncate(name, DISCORD_MAX_FIELD_NAME_CHARS, "...");
+        let value = truncate(&value, DISCORD_MAX_FIELD_VALUE_CHARS, "...");
+        let chars = name.chars().count() + value.chars().count();
+        (json!({ "name": name, "value": value }), chars)
+    };
+    let mut fields = Vec::new();
+    let mut total = title.chars().count() + description.chars().count();
+
+    if let Some(pattern) = &commit_match.message_pattern {
+        let (message, chars) = field(
+            "Message",
+            format!(
+                "{}\nPattern matched: {}",
+                markdown_escape(&commit_match.summary),
+                patterns_list(std::slice::from_ref(pattern)),
+            ),
+        );
+        fields.push(message);
+        total += chars;
+    }
+
+    // the last field is kept to tell how many files didn't fit, within both
+    // the number of fields and the size of the embed
+    let room = DISCORD_MAX_FIELDS - fields.len();
+    let (_, more_files_chars) = field("Other files", more_files(commit_match.files.len()));
+    let mut shown = 0;
+    for (index, file) in commit_match.files.iter().enumerate() {
+        let is_last = index + 1 == commit_match.files.len();
+        let (reserved_fields, reserved_chars) = if is_last {
+            (0, 0)
+        } else {
+            (1, more_files_chars)
+        };
+        if shown + 1 + reserved_fields > room {
+            break;
+        }
+
+        let mut value = format!("`+{}` `-{}`", file.additions, file.deletions);
+        if !file.patterns.is_empty() {
+            value.push_str(&format!(
+                "\nPatterns matched: {}",
+                patterns_list(&file.patterns)
+            ));
+        }
+        if let Some(excerpt) = &file.excerpt {
+            let fences = "\n```diff\n\n```";
+            if let Some(budget) = excerpt_budget(&value, fences, DISCORD_MAX_FIELD_VALUE_CHARS) {
+                let excerpt = code_excerpt_within(excerpt, budget);
+                value.push_str(&format!("\n```diff\n{}\n```", excerpt));
+            }
+        }
+        let (file_field, chars) = field(&markdown_escape(&file.filename), value);
+        if total + chars + reserved_chars > DISCORD_MAX_EMBED_CHARS {
+            break;
+        }
+        fields.push(file_field);
+        total += chars;
+        shown += 1;
+    }
+    if commit_match.files.len() > shown {
+        let (more_files, _) = field("Other files", more_files(commit_match.files.len() - shown));
+        fields.push(more_files);
+    }
+
+    json!({
+        "username": USERNAME,
+        "embeds": [{
+            "title": title,
+            "url": commit_match.html_url,
+            "description": description,
+            "color": DISCORD_COLOR,
+            "fields": fields,
+        }],
+    })
+}
+
+/// Markdown flavour understood by both Mattermost and Matrix clients, within
+/// the size of the Mattermost posts
+fn markdown(commit_match: &CommitMatch) -> String {
+    let author = commit_match.author.as_deref().unwrap_or("unknown");
+    let mut text = format!(
+        "#### New matching commit in [{}]({})\n[`{}`]({}) by {}\n",
+        markdown_escape(&commit_match.repository),
+        repository_url(commit_match),
+        commit_match.short_sha(),
+        commit_match.html_url,
+        markdown_escape(author)
+    );
+
+    if let Some(pattern) = &commit_match.message_pattern {
+        text.push_str(&format!(
+            "\n**Message:** {}\n**Pattern matched:** {}\n",
+            markdown_escape(&commit_match.summary),
+            patterns_list(std::slice::from_ref(pattern))
+        ));
+    }
+
+    // room is kept to tell how many files didn't fit
+    let more_files_chars = more_files(commit_match.files.len()).chars().count() + 1;
+    let mut total = text.chars().count();
+    let mut shown = 0;
+    for (index, file) in commit_match.files.iter().enumerate() {
+        let mut section = format!(
+            "\n**{}** `+{}` `-{}`\n",
+            markdown_escape(&file.filename),
+            file.additions,
+            file.deletions
+        );
+        if !file.patterns.is_empty() {
+            section.push_str(&format!(
+                "**Patterns matched:** {}\n",
+                patterns_list(&file.patterns)
+            ));
+        }
+        if let Some(excerpt) = &file.excerpt {
+            section.push_str(&format!("```diff\n{}\n```\n", code_excerpt(excerpt)));
+        }
+
+        let is_last = index + 1 == commit_match.files.len();
+        let reserved_chars = if is_last { 0 } else { more_files_chars };
+        let chars = section.chars().count();
+        if total + chars + reserved_chars > MATTERMOST_MAX_POST_CHARS {
+            break;
+        }
+        text.push_str(&section);
+        total += chars;
+        shown += 1;
+    }
+    if commit_match.files.len() > shown {
+        text.push('\n');
+        text.push_str(&more_files(commit_match.files.len() - shown));
+    }
+    text
+}
+
+/// HTML rendering of a match, also used by the email bodies
+pub fn html(commit_match: &CommitMatch) -> String {
+    let author = commit_match.author.as_deref().unwrap_or("unknown");
+    let mut html = format!(
+        "<h4>New matching commit in <a href=\"{}\">{}</a></h4><p><a href=\"{}\"><code>{}</code></a> by {}</p>",
+        html_escape(&repository_url(commit_match)),
+        html_escape(&commit_match.repository),
+        html_escape(&commit_match.html_url),
+        commit_match.short_sha(),
+        html_escape(author)
+    );
+
+    let code_list = |patterns: &[String]| {
+        patterns
+            .iter()
+            .map(|pattern| format!("<code>{}</code>", html_escape(pattern)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    if let Some(pattern) = &commit_match.message_pattern {
+        html.push_str(&format!(
+            "<p><b>Message:</b> {}<br><b>Pattern matched:</b> {}</p>",
+            html_escape(&commit_match.summary),
+            code_list(std::slice::from_ref(pattern))
+        ));
+    }
+
+    for file in &commit_match.files {
+        html.push_str(&format!(
+            "<p><b>{}</b> <code>+{}</code> <code>-{}</code>",
+            html_escape(&file.filename),
+            file.additions,
+            file.deletions
+        ));
+        if !file.patterns.is_empty() {
+            html.push_str(&format!(
+                "<br><b>Patterns matched:</b> {}",
+                code_list(&file.patterns)
+            ));
+        }
+        html.push_str("</p>");
+        if let Some(excerpt) = &file.excerpt {
+            html.push_str(&format!(
+                "<pre><code class=\"language-diff\">{}</code></pre>",
+                html_escape(&code_excerpt(excerpt))
+            ));
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+
+    /// A match with more files than any chat platform can show, each with a
+    /// long name and a long excerpt
+    fn huge_match() -> CommitMatch {
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        let file = commit_match.files[0].clone();
+        commit_match.files = (0..60)
+            .map(|index| {
+                let mut file = file.clone();
+                file.filename = format!("{}/{}.c", "dir".repeat(100), index);
+                file.excerpt = Some("+\tkfree(obj); /* UAF */\n".repeat(200));
+                file
+            })
+            .collect();
+        commit_match
+    }
+
+    #[test]
+    fn keeps_discord_embeds_within_the_limits() {
+        let message = render(ChatFormat::Discord, &huge_match());
+        let fields = message["embeds"][0]["fields"].as_array().unwrap();
+        assert!(fields.len() <= DISCORD_MAX_FIELDS);
+        for field in fields {
+            let name = field["name"].as_str().unwrap();
+            let value = field["value"].as_str().unwrap();
+            assert!(name.chars().count() <= DISCORD_MAX_FIELD_NAME_CHARS);
+            assert!(value.chars().count() <= DISCORD_MAX_FIELD_VALUE_CHARS);
+        }
+        assert!(fields[0]["value"].as_str().unwrap().ends_with("\n```"));
+
+        // the fields are cut short by the size of the embed
+        let embed = &message["embeds"][0];
+        let total = ["title", "description"]
+            .iter()
+            .map(|key| embed[key].as_str().unwrap().chars().count())
+            .chain(fields.iter().flat_map(|field| {
+                ["name", "value"]
+                    .iter()
+                    .map(|key| field[key].as_str().unwrap().chars().count())
+            }))
+            .sum::<usize>();
+        assert!(total <= DISCORD_MAX_EMBED_CHARS);
+        let more_files = fields.last().unwrap()["value"].as_str().unwrap();
+        assert_eq!(
+            more_files,
+            format!("...and {} more files", 60 - (fields.len() - 1))
+        );
+    }
+
+    #[test]
+    fn keeps_discord_embeds_to_25_fields() {
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        let file = commit_match.files[0].clone();
+        commit_match.files = vec![file; 30];
+
+        let message = render(ChatFormat::Discord, &commit_match);
+        let fields = message["embeds"][0]["fields"].as_array().unwrap();
+        assert_eq!(fields.len(), DISCORD_MAX_FIELDS);
+        assert_eq!(fields[24]["value"], "...and 6 more files");
+    }
+
+    #[test]
+    fn keeps_mattermost_posts_within_the_limits() {
+        let message = render(ChatFormat::Mattermost, &huge_match());
+        let text = message["text"].as_str().unwrap();
+        assert!(text.chars().count() <= MATTERMOST_MAX_POST_CHARS);
+        assert!(text.ends_with(" more files"));
+
+        // a match that fits is left whole
+        let message = render(ChatFormat::Mattermost, &commit_match("abc123", "mm/slab.c"));
+        assert!(message["text"].as_str().unwrap().ends_with("```\n"));
+    }
+
+    #[test]
+    fn keeps_slack_blocks_within_the_limits() {
+        let message = render(ChatFormat::Slack, &huge_match());
+        let blocks = message["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), SLACK_MAX_BLOCKS);
+        for block in blocks {
+            let text = block["text"]["text"].as_str().unwrap();
+            assert!(text.chars().count() <= SLACK_MAX_SECTION_CHARS);
+        }
+        assert!(blocks[1]["text"]["text"].as_str().unwrap().ends_with("```"));
+        assert_eq!(blocks[49]["text"]["text"], "...and 12 more files");
+    }
+
+    #[test]
+    fn escapes_markdown() {
+        let mut commit_match = commit_match("abc123", "drivers/__init__.c");
+        commit_match.summary = "Revert *everything* @everyone".to_owned();
+        commit_match.message_pattern = Some("Revert".to_owned());
+
+        let message = render(ChatFormat::Discord, &commit_match);
+        let fields = &message["embeds"][0]["fields"];
+        assert!(fields[0]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with(r"Revert \*everything\* \@everyone"));
+        assert_eq!(fields[1]["name"], r"drivers/\_\_init\_\_.c");
+
+        let message = render(ChatFormat::Mattermost, &commit_match);
+        let text = message["text"].as_str().unwrap();
+        assert!(text.contains(r"**Message:** Revert \*everything\* \@everyone"));
+        assert!(text.contains(r"**drivers/\_\_init\_\_.c**"));
+    }
+
+    #[test]
+    fn guards_the_code_blocks() {
+        assert_eq!(code_excerpt("+```"), "+`\u{200b}``");
+        assert!(!code_excerpt("+````").contains("```"));
+        let excerpt = code_excerpt(&"x".repeat(1000));
+        assert_eq!(excerpt.chars().count(), MAX_EXCERPT_CHARS);
+        assert!(excerpt.ends_with("\n..."));
+    }
+}
@@ -0,0 +1,222 @@
+use super::payload::MatchPayload;
+use super::Notifier;
+use crate::config::parse_duration;
+use crate::matcher::CommitMatch;
+use crate::terminal::TerminalDisplay;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::process::Stdio;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandConfig {
+    /// The program followed by its arguments
+    command: Vec<String>,
+    /// How long the command may run before it gets killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<String>,
+}
+
+/// Runs a command for every match, with the match as JSON (see
+/// [`MatchPayload`]) on its stdin and its key fields as `VULNGREP_*`
+/// environment variables
+pub struct CommandNotifier {
+    name: String,
+    command: Vec<String>,
+    timeout: Duration,
+    display: Rc<TerminalDisplay>,
+}
+
+impl CommandNotifier {
+    const DEFAULT_TIMEOUT: &'static str = "60s";
+
+    pub fn new(
+        name: String,
+        config: CommandConfig,
+        display: Rc<TerminalDisplay>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if config.command.is_empty() {
+            return Err("No command given".into());
+        }
+        let timeout = parse_duration(config.timeout.as_deref().unwrap_or(Self::DEFAULT_TIMEOUT))?;
+        if timeout == 0 {
+            // every command would be killed right away
+            return Err("The command timeout must be at least 1s".into());
+        }
+        Ok(Self {
+            name,
+            command: config.command,
+            timeout: Duration::from_secs(timeout),
+            display,
+        })
+    }
+}
+
+/// The environment variables describing a match, lists are newline separated
+fn environment(commit_match: &CommitMatch) -> Vec<(&'static str, String)> {
+    let files: Vec<&str> = commit_match
+        .files
+        .iter()
+        .map(|file| file.filename.as_str())
+        .collect();
+    vec![
+        ("VULNGREP_REPOSITORY", commit_match.repository.clone()),
+        ("VULNGREP_SHA", commit_match.sha.clone()),
+        ("VULNGREP_URL", commit_match.html_url.clone()),
+        ("VULNGREP_SUMMARY", commit_match.summary.clone()),
+        (
+            "VULNGREP_AUTHOR",
+            commit_match.author.clone().unwrap_or_default(),
+        ),
+        ("VULNGREP_SEVERITY", commit_match.severity.to_string()),
+        ("VULNGREP_PATTERNS", commit_match.patterns().join("\n")),
+        ("VULNGREP_FILES", files.join("\n")),
+    ]
+}
+
+#[async_trait(?Send)]
+impl Notifier for CommandNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        let (program, args) = self.command.split_first().ok_or("No command given")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .envs(environment(commit_match))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let payload = serde_json::to_vec(&MatchPayload::from(commit_match))?;
+        let stdin = child.stdin.take();
+        let stderr = child.stderr.take();
+        // the input is written while the output is read, so that neither the
+        // command nor the notifier waits for the other one forever
+        let run = async {
+            let write = async {
+                if let Some(mut stdin) = stdin {
+                    // a command that doesn't read its input is fine
+                    let _ = stdin.write_all(&payload).await;
+                    // dropping stdin closes it, so the command sees the end of the input
+                }
+            };
+            let read = async {
+                let mut errors = Vec::new();
+                if let Some(mut stderr) = stderr {
+                    stderr.read_to_end(&mut errors).await?;
+                }
+                Ok::<_, std::io::Error>(errors)
+            };
+            let ((), errors, status) = tokio::join!(write, read, child.wait());
+            Ok::<_, std::io::Error>((status?, errors?))
+        };
+
+        // the input may block as well, so it's part of the time the command gets
+        let result = tokio::time::timeout(self.timeout, run).await;
+        let (exit_status, stderr) = match result {
+            Ok(output) => output?,
+            Err(_) => {
+                child.kill().await?;
+                return Err(
+                    format!("{} timed out after {}s", program, self.timeout.as_secs()).into(),
+                );
+            }
+        };
+
+        let status = match exit_status.code() {
+            Some(code) => format!("exited with code {}", code),
+            None => "was killed by a signal".to_owned(),
+        };
+        if !exit_status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(match stderr.trim().lines().last() {
+                Some(line) => format!("{} {}: {}", program, status, line),
+                None => format!("{} {}", program, status),
+            }
+            .into());
+        }
+
+        self.display.display_success(&format!(
+            "Notifier {} ran {} for {}, it {}",
+            self.name,
+            program,
+            commit_match.short_sha(),
+            status
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+    use std::time::Instant;
+
+    fn notifier(command: &[&str], timeout: &str) -> CommandNotifier {
+        let config = CommandConfig {
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            timeout: Some(timeout.to_owned()),
+        };
+        CommandNotifier::new(
+            "command".to_owned(),
+            config,
+            Rc::new(TerminalDisplay::new()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn passes_the_match_on_stdin_and_in_the_environment() {
+        let script = r#"read -r payload; case "$payload" in *'"sha":"abc123"'*) ;; *) exit 3;; esac; test "$VULNGREP_FILES" = mm/slab.c"#;
+        let notifier = notifier(&["sh", "-c", script], "10s");
+        notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_the_last_line_of_stderr() {
+        let notifier = notifier(
+            &["sh", "-c", "echo first >&2; echo last >&2; exit 2"],
+            "10s",
+        );
+        let err = notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sh exited with code 2: last");
+    }
+
+    #[tokio::test]
+    async fn kills_a_command_that_does_not_read_its_input() {
+        let notifier = notifier(&["sleep", "30"], "1s");
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        // more than a pipe holds, so writing the input blocks
+        commit_match.files[0].excerpt = Some("x".repeat(1 << 20));
+
+        let started = Instant::now();
+        let err = notifier.notify(&commit_match).await.unwrap_err();
+        assert_eq!(err.to_string(), "sleep timed out after 1s");
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn rejects_a_zero_timeout() {
+        let config = CommandConfig {
+            command: vec!["true".to_owned()],
+            timeout: Some("0s".to_owned()),
+        };
+        let display = Rc::new(TerminalDisplay::new());
+        assert!(CommandNotifier::new("command".to_owned(), config, display).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use super::template::{Defaults, Template, TemplateConfig};
+use super::{Notifier, Suppressed};
+use crate::config::parse_duration;
+use crate::matcher::CommitMatch;
+use async_trait::async_trait;
+use notify_rust::{Notification as SystemNotification, Timeout};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DesktopConfig {
+    /// How long the popup stays on screen, e.g. `30s`, or `never`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<String>,
+    /// Name of the sound played along with the popup, or `none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sound: Option<String>,
+    /// Adds the "Open commit" and "Mark ignored" buttons, on Linux only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    actions: Option<bool>,
+}
+
+/// Upper bound on the number of lines in the body of a popup
+const MAX_LINES: usize = 8;
+/// Upper bound on the number of popups with buttons at once, the following
+/// ones have none until some of them are closed
+#[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+const MAX_WAITING: usize = 8;
+
+const DEFAULT_TITLE: &str = "🔍 New matching commit in {{ repository }}";
+const DEFAULT_BODY: &str = r#"👤 {{ author }}
+🔗 {{ short_sha }}
+{% if message_pattern %}
+💬 {{ message_pattern }}
+{% endif %}
+{% for file in files %}
+📄 {{ file.filename }}{{ " (" ~ file.patterns | join(", ") ~ ")" if file.patterns }}
+{% endfor %}
+"#;
+const DEFAULT_SUMMARY_TITLE: &str = "🔍 {{ count }} new matching commits";
+const DEFAULT_SUMMARY_BODY: &str = r#"{% for match in matches %}
+🔗 {{ match.repository }} {{ match.short_sha }}
+{% endfor %}
+"#;
+
+/// Pops a system notification for every matching commit
+pub struct DesktopNotifier {
+    name: String,
+    timeout: Timeout,
+    sound: Option<String>,
+    /// Only while watching, as nobody is left to act on the buttons of the
+    /// popups once a one-off run is over
+    #[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+    actions: bool,
+    /// Popups whose buttons are waited for, by a thread each
+    #[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+    waiting: Arc<AtomicUsize>,
+    template: Template,
+}
+
+impl DesktopNotifier {
+    /// A sound of the freedesktop sound theme
+    #[cfg(all(unix, not(target_os = "macos")))]
+    const SOUND: &'static str = "message-new-instant";
+    /// One of the sounds of /System/Library/Sounds
+    #[cfg(target_os = "macos")]
+    const SOUND: &'static str = "Glass";
+    #[cfg(target_os = "windows")]
+    const SOUND: &'static str = "Mail";
+
+    pub fn new(
+        name: String,
+        config: DesktopConfig,
+        template: Option<&TemplateConfig>,
+        watching: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let timeout = match config.timeout.as_deref() {
+            None | Some("never") => Timeout::Never,
+            Some(timeout) => {
+                let milliseconds = u32::try_from(parse_duration(timeout)?)
+                    .ok()
+                    .and_then(|secs| secs.checked_mul(1000))
+                    .ok_or_else(|| format!("Popup timeout too long: {}", timeout))?;
+                Timeout::Milliseconds(milliseconds)
+            }
+        };
+        let sound = match config.sound.as_deref() {
+            None => Some(Self::SOUND.to_owned()),
+            Some("none") => None,
+            Some(sound) => Some(sound.to_owned()),
+        };
+
+        Ok(Self {
+            name,
+            timeout,
+            sound,
+            actions: watching && config.actions.unwrap_or(true),
+            waiting: Arc::default(),
+            template: Template::new(
+                template,
+                Defaults {
+                    title: Some(DEFAULT_TITLE),
+                    body: Some(DEFAULT_BODY),
+                    summary_title: Some(DEFAULT_SUMMARY_TITLE),
+                    summary_body: Some(DEFAULT_SUMMARY_BODY),
+                    ..Defaults::default()
+                },
+            )?,
+        })
+    }
+
+    fn popup(&self, summary: &str, body: &str) -> SystemNotification {
+        let mut popup = SystemNotification::new();
+        popup.summary(summary).body(body).timeout(self.timeout);
+        if let Some(sound) = &self.sound {
+            popup.sound_name(sound);
+        }
+        popup
+    }
+
+    /// Shows a popup about a single commit, and acts on the button the user
+    /// clicks in the background
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn show_commit(
+        &self,
+        summary: &str,
+        body: &str,
+        commit_match: &CommitMatch,
+    ) -> Result<(), Box<dyn Error>> {
+        use crate::triage::{TriageState, TriageStore};
+
+        let mut popup = self.popup(summary, body);
+        // popups that never time out keep their thread until they're closed
+        if !self.actions || self.waiting.load(Ordering::Relaxed) >= MAX_WAITING {
+            popup.show()?;
+            return Ok(());
+        }
+
+        let handle = popup
+            .action("default", "Open commit")
+            .action("open", "Open commit")
+            .action("ignore", "Mark ignored")
+            .show()?;
+        let repository = commit_match.repository.clone();
+        let sha = commit_match.sha.clone();
+        let html_url = commit_match.html_url.clone();
+        let waiting = self.waiting.clone();
+        waiting.fetch_add(1, Ordering::Relaxed);
+        // waiting for the user blocks, up to the popup being clicked or closed,
+        // and nobody is left to report errors to
+        std::thread::spawn(move || {
+            handle.wait_for_action(|action| match action {
+                "default" | "open" => {
+                    let _ = open::that(&html_url);
+                }
+                "ignore" => {
+                    let _ = TriageStore::mark(&repository, &sha, TriageState::Ignored);
+                }
+                _ => (),
+            });
+            waiting.fetch_sub(1, Ordering::Relaxed);
+        });
+        Ok(())
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn show_commit(
+        &self,
+        summary: &str,
+        body: &str,
+        _commit_match: &CommitMatch,
+    ) -> Result<(), Box<dyn Error>> {
+        self.popup(summary, body).show()?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        let summary = self.template.title(commit_match)?.unwrap_or_default();
+        let body = self.template.body(commit_match)?.unwrap_or_default();
+        let lines = body.lines().map(str::to_owned).collect();
+
+        self.show_commit(&summary, &truncate_lines(lines), commit_match)
+    }
+
+    async fn notify_summary(&self, matches: &[CommitMatch]) -> Result<(), Box<dyn Error>> {
+        let summary = self.template.summary_title(matches)?.unwrap_or_default();
+        let body = self.template.summary_body(matches)?.unwrap_or_default();
+        let lines = body.lines().map(str::to_owned).collect();
+
+        self.popup(&summary, &truncate_lines(lines)).show()?;
+        Ok(())
+    }
+
+    async fn notify_suppressed(&self, suppressed: &Suppressed) -> Result<(), Box<dyn Error>> {
+        let summary = format!("🔕 {0}", suppressed);
+        self.popup(&summary, "").show()?;
+        Ok(())
+    }
+}
+
+/// Keeps the popup readable, by cutting the lines down to a few
+fn truncate_lines(mut lines: Vec<String>) -> String {
+    if lines.len() > MAX_LINES {
+        let hidden = lines.len() - (MAX_LINES - 1);
+        lines.truncate(MAX_LINES - 1);
+        lines.push(format!("… and {0} more", hidden));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(timeout: &str) -> DesktopConfig {
+        DesktopConfig {
+            timeout: Some(timeout.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn converts_the_timeout_to_milliseconds() {
+        let notifier =
+            DesktopNotifier::new("desktop".to_owned(), config("30s"), None, true).unwrap();
+        assert_eq!(notifier.timeout, Timeout::Milliseconds(30_000));
+        let notifier =
+            DesktopNotifier::new("desktop".to_owned(), config("never"), None, true).unwrap();
+        assert_eq!(notifier.timeout, Timeout::Never);
+    }
+
+    #[test]
+    fn rejects_timeouts_too_long() {
+        assert!(DesktopNotifier::new("desktop".to_owned(), config("50d"), None, true).is_err());
+        assert!(DesktopNotifier::new("desktop".to_owned(), config("60000d"), None, true).is_err());
+    }
+
+    #[test]
+    fn only_has_buttons_while_watching() {
+        let notifier =
+            DesktopNotifier::new("desktop".to_owned(), config("30s"), None, false).unwrap();
+        assert!(!notifier.actions);
+    }
+}
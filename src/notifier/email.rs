@@ -0,0 +1,449 @@
+use super::template::{Defaults, Template, TemplateConfig};
+use super::{chat, Notifier, Suppressed};
+use crate::config::Notification;
+use crate::matcher::CommitMatch;
+use async_trait::async_trait;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTls {
+    /// Upgrades a plain connection, usually on port 587
+    #[default]
+    Starttls,
+    /// Implicit TLS, usually on port 465
+    Tls,
+    /// No encryption at all, only meant for local servers
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(default)]
+    tls: EmailTls,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    from: String,
+    /// Recipients of the matches of notifications without `recipients`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    to: Vec<String>,
+}
+
+/// Sends an email for every match, or a digest of them once per cycle with
+/// the `summary` option of the notifier
+pub struct EmailNotifier {
+    name: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    /// Recipients of specific repositories, taken from the notifications that
+    /// route their matches to this sink
+    recipients: HashMap<String, Vec<Mailbox>>,
+    template: Template,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+const DEFAULT_TITLE: &str = "New matching commit in {{ repository }}";
+const DEFAULT_SUMMARY_TITLE: &str = "{{ count }} new matching commits";
+const DEFAULT_BODY: &str = r#"Repository: {{ repository }}
+Commit: {{ url }}
+Author: {{ author }}
+{% if message_pattern %}
+Message: {{ summary }}
+Pattern matched: {{ message_pattern }}
+{% endif %}
+{% for file in files %}
+File: {{ file.filename }}, Additions: {{ file.additions }}, Deletions: {{ file.deletions }}
+{% for pattern in file.patterns %}
+Pattern matched: {{ pattern }}
+{% endfor %}
+{% if file.excerpt %}
+
+{{ file.excerpt }}
+{% endif %}
+{% endfor %}
+"#;
+
+fn parse_mailboxes(addresses: &[String]) -> Result<Vec<Mailbox>, Box<dyn Error>> {
+    Ok(addresses
+        .iter()
+        .map(|address| address.parse())
+        .collect::<Result<_, _>>()?)
+}
+
+impl EmailNotifier {
+    pub fn new(
+        name: String,
+        config: EmailConfig,
+        template: Option<&TemplateConfig>,
+        notifications: &[Notification],
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut transport = match config.tls {
+            EmailTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+            }
+            EmailTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            EmailTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        };
+        if let Some(port) = config.port {
+            transport = transport.port(port);
+        }
+        if let (Some(username), Some(password)) = (config.username, config.password) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+
+        let mut recipients: HashMap<String, Vec<Mailbox>> = HashMap::new();
+        for notification in notifications {
+            if !notification.routes_to(&name) {
+                continue;
+            }
+            if let Some(addresses) = notification.recipients() {
+                recipients
+                    .entry(notification.repository().uri())
+                    .or_default()
+                    .extend(parse_mailboxes(addresses)?);
+            }
+        }
+        if config.to.is_empty() && recipients.is_empty() {
+            return Err("No recipients".into());
+        }
+
+        Ok(Self {
+            name,
+            from: config.from.parse()?,
+            to: parse_mailboxes(&config.to)?,
+            recipients,
+            template: Template::new(
+                template,
+                Defaults {
+                    title: Some(DEFAULT_TITLE),
+                    body: Some(DEFAULT_BODY),
+                    summary_title: Some(DEFAULT_SUMMARY_TITLE),
+                    ..Defaults::default()
+                },
+            )?,
+            transport: transport.build(),
+        })
+    }
+
+    /// Who receives the matches of a repository
+    fn recipients_of(&self, repository: &str) -> &[Mailbox] {
+        self.recipients.get(repository).unwrap_or(&self.to)
+    }
+
+    async fn send(
+        &self,
+        to: &[Mailbox],
+        subject: String,
+        plain: String,
+        html: String,
+    ) -> Result<(), Box<dyn Error>> {
+        if to.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in to {
+            message = message.to(to.clone());
+        }
+
+        let message = message.multipart(MultiPart::alternative_plain_html(plain, html))?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    async fn send_matches(
+        &self,
+        to: &[Mailbox],
+        subject: String,
+        matches: &[&CommitMatch],
+    ) -> Result<(), Box<dyn Error>> {
+        if to.is_empty() {
+            // the repositories have no recipients, and the sink has no default ones
+            let mut repositories: Vec<&str> = matches
+                .iter()
+                .map(|commit_match| commit_match.repository.as_str())
+                .collect();
+            repositories.sort();
+            repositories.dedup();
+            return Err(format!(
+                "No recipients for the matches of {}, set the recipients of its notification or the `to` of the sink",
+                repositories.join(", ")
+            )
+            .into());
+        }
+
+        let mut plain = Vec::new();
+        let mut html = Vec::new();
+        for commit_match in matches {
+            plain.push(self.template.body(commit_match)?.unwrap_or_default());
+            html.push(match self.template.html(commit_match)? {
+                Some(html) => html,
+                None => chat::html(commit_match),
+            });
+        }
+
+        let plain = plain.join("\n");
+        let html = format!("<html><body>{}</body></html>", html.join("<hr>"));
+        self.send(to, subject, plain, html).await
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        let subject = self.template.title(commit_match)?.unwrap_or_default();
+        self.send_matches(
+            self.recipients_of(&commit_match.repository),
+            subject,
+            &[commit_match],
+        )
+        .await
+    }
+
+    async fn notify_summary(&self, matches: &[CommitMatch]) -> Result<(), Box<dyn Error>> {
+        // one digest per group of recipients, each with the matches they receive
+        let mut digests: Vec<(&[Mailbox], Vec<&CommitMatch>)> = Vec::new();
+        for commit_match in matches {
+            let to = self.recipients_of(&commit_match.repository);
+            match digests.iter_mut().find(|(recipients, _)| *recipients == to) {
+                Some((_, matches)) => matches.push(commit_match),
+                None => digests.push((to, vec![commit_match])),
+            }
+        }
+
+        // a group without recipients doesn't keep the others from their digest
+        let mut result = Ok(());
+        for (to, matches) in digests {
+            let subject = match matches.as_slice() {
+                [commit_match] => self.template.title(commit_match)?,
+                matches => self.template.summary_title(matches.iter().copied())?,
+            };
+            let subject = subject.unwrap_or_default();
+            if let Err(err) = self.send_matches(to, subject, &matches).await {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    async fn notify_suppressed(&self, suppressed: &Suppressed) -> Result<(), Box<dyn Error>> {
+        // everybody who missed some of the matches hears about it
+        let mut to: Vec<Mailbox> = Vec::new();
+        for repository in suppressed.repositories.keys() {
+            for mailbox in self.recipients_of(repository) {
+                if !to.contains(mailbox) {
+                    to.push(mailbox.clone());
+                }
+            }
+        }
+
+        let text = suppressed.to_string();
+        let html = format!("<html><body><p>{}</p></body></html>", text);
+        self.send(&to, text.clone(), text, html).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// An email as received by the local SMTP catcher
+    struct Email {
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    /// Speaks just enough SMTP to accept the emails, and hands them over
+    async fn catch_emails() -> (u16, mpsc::UnboundedReceiver<Email>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                writer.write_all(b"220 catcher ESMTP\r\n").await.unwrap();
+                let mut email = Email {
+                    recipients: Vec::new(),
+                    data: String::new(),
+                };
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let reply: &[u8] = if let Some(to) = line.strip_prefix("RCPT TO:") {
+                        email
+                            .recipients
+                            .push(to.trim_matches(['<', '>']).to_owned());
+                        b"250 OK\r\n"
+                    } else if line == "DATA" {
+                        writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            email.data.push_str(&line);
+                            email.data.push('\n');
+                        }
+                        sender
+                            .send(std::mem::replace(
+                                &mut email,
+                                Email {
+                                    recipients: Vec::new(),
+                                    data: String::new(),
+                                },
+                            ))
+                            .unwrap();
+                        b"250 Queued\r\n"
+                    } else if line == "QUIT" {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    } else {
+                        b"250 OK\r\n"
+                    };
+                    writer.write_all(reply).await.unwrap();
+                }
+            }
+        });
+        (port, receiver)
+    }
+
+    fn notifier(port: u16, name: &str, notifications: &[Notification]) -> EmailNotifier {
+        let config = EmailConfig {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            tls: EmailTls::None,
+            username: None,
+            password: None,
+            from: "Vulngrep <vulngrep@example.com>".to_owned(),
+            to: vec!["security@example.com".to_owned()],
+        };
+        EmailNotifier::new(name.to_owned(), config, None, notifications).unwrap()
+    }
+
+    fn notification(yaml: &str) -> Notification {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_an_email_per_match() {
+        let (port, mut emails) = catch_emails().await;
+        let notifier = notifier(port, "email", &[]);
+
+        notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+
+        let email = emails.recv().await.unwrap();
+        assert_eq!(email.recipients, ["security@example.com"]);
+        assert!(email
+            .data
+            .contains("Subject: New matching commit in torvalds/linux"));
+        assert!(email.data.contains("Content-Type: text/plain"));
+        assert!(email.data.contains("Content-Type: text/html"));
+        assert!(email
+            .data
+            .contains("File: mm/slab.c, Additions: 1, Deletions: 0"));
+    }
+
+    #[tokio::test]
+    async fn sends_a_digest_per_group_of_recipients() {
+        let (port, mut emails) = catch_emails().await;
+        let notifications = [notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             recipients: [kernel@example.com]",
+        )];
+        let notifier = notifier(port, "email", &notifications);
+        let mut other = commit_match("def456", "src/lib.rs");
+        other.repository = "rust-lang/rust".to_owned();
+
+        let matches = [
+            commit_match("abc123", "mm/slab.c"),
+            other,
+            commit_match("abc456", "mm/slub.c"),
+        ];
+        notifier.notify_summary(&matches).await.unwrap();
+
+        let email = emails.recv().await.unwrap();
+        assert_eq!(email.recipients, ["kernel@example.com"]);
+        assert!(email.data.contains("Subject: 2 new matching commits"));
+        let email = emails.recv().await.unwrap();
+        assert_eq!(email.recipients, ["security@example.com"]);
+        assert!(email
+            .data
+            .contains("Subject: New matching commit in rust-lang/rust"));
+    }
+
+    #[tokio::test]
+    async fn only_uses_the_recipients_of_the_notifications_routed_to_the_sink() {
+        let (port, mut emails) = catch_emails().await;
+        let notifications = [notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             recipients: [kernel@example.com]\n\
+             notify: [digest]",
+        )];
+        let digest = notifier(port, "digest", &notifications);
+        let on_call = notifier(port, "on-call", &notifications);
+
+        digest
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+        on_call
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            emails.recv().await.unwrap().recipients,
+            ["kernel@example.com"]
+        );
+        assert_eq!(
+            emails.recv().await.unwrap().recipients,
+            ["security@example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_without_recipients() {
+        let (port, mut emails) = catch_emails().await;
+        let notifications = [notification(
+            "repository: { owner: torvalds, name: linux }\n\
+             recipients: [kernel@example.com]",
+        )];
+        let mut notifier = notifier(port, "email", &notifications);
+        notifier.to = Vec::new();
+        let mut other = commit_match("def456", "src/lib.rs");
+        other.repository = "rust-lang/rust".to_owned();
+
+        let err = notifier.notify(&other).await.unwrap_err();
+        assert!(err.to_string().contains("rust-lang/rust"));
+
+        // the repositories with recipients still get their digest
+        let matches = [other, commit_match("abc123", "mm/slab.c")];
+        let err = notifier.notify_summary(&matches).await.unwrap_err();
+        assert!(err.to_string().contains("rust-lang/rust"));
+        assert_eq!(
+            emails.recv().await.unwrap().recipients,
+            ["kernel@example.com"]
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use super::{chat, Notifier};
+use crate::config::Notification;
+use crate::matcher::{self, CommitMatch};
+use crate::store;
+use crate::terminal::TerminalDisplay;
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedConfig {
+    /// Where the feed is written. With a `{repository}` or `{rule}`
+    /// placeholder, there is one feed per repository or per pattern instead
+    path: String,
+    /// Where the feed is published, with the same placeholders as the path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// How many of the most recent matches a feed holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_entries: Option<usize>,
+}
+
+/// How the matches are split between the feeds
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Split {
+    None,
+    Repository,
+    Rule,
+}
+
+impl Split {
+    const REPOSITORY: &'static str = "{repository}";
+    const RULE: &'static str = "{rule}";
+
+    /// The feeds a match goes to
+    fn keys(&self, commit_match: &CommitMatch) -> Vec<String> {
+        match self {
+            Split::None => vec![String::new()],
+            Split::Repository => vec![commit_match.repository.clone()],
+            Split::Rule => {
+                let mut keys: Vec<String> = commit_match
+                    .patterns()
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect();
+                // files that matched by path alone
+                if commit_match
+                    .files
+                    .iter()
+                    .any(|file| file.patterns.is_empty())
+                {
+                    keys.push(String::new());
+                }
+                keys
+            }
+        }
+    }
+
+    /// The name of the feed of a key, before any numbering
+    fn slug(&self, key: &str) -> String {
+        match self {
+            Split::None => String::new(),
+            Split::Repository => key.replace('/', "-"),
+            Split::Rule if key.is_empty() => PATH_SLUG.to_owned(),
+            Split::Rule => matcher::slug(key),
+        }
+    }
+
+    /// Fills the placeholder of a path or URL in with the slug of a feed
+    fn expand(&self, template: &str, slug: &str) -> String {
+        match self {
+            Split::None => template.to_owned(),
+            Split::Repository => template.replace(Self::REPOSITORY, slug),
+            Split::Rule => template.replace(Self::RULE, slug),
+        }
+    }
+
+    fn title(&self, key: &str) -> String {
+        match self {
+            Split::None => "Vulngrep matches".to_owned(),
+            Split::Repository => format!("Vulngrep matches in {}", key),
+            Split::Rule if key.is_empty() => "Vulngrep matches of watched paths".to_owned(),
+            Split::Rule => format!("Vulngrep matches of {}", key),
+        }
+    }
+}
+
+/// The slug of the feed of the files that matched by path alone
+const PATH_SLUG: &str = "path";
+
+/// A match that has been published, along with when it was
+#[derive(Debug, Serialize, Deserialize)]
+struct FeedEntry {
+    published: DateTime<Utc>,
+    #[serde(rename = "match")]
+    commit_match: CommitMatch,
+}
+
+/// The recent matches of every feed of a sink, so that the feeds survive restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FeedStore {
+    /// Oldest first, keyed by repository or pattern when the feeds are split
+    feeds: BTreeMap<String, Vec<FeedEntry>>,
+    /// The slug of every feed, keyed the same way. They are kept so that the
+    /// feeds don't move when another one comes along
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    slugs: BTreeMap<String, String>,
+}
+
+impl FeedStore {
+    const FEEDS_DIRNAME: &'static str = "feeds";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the store file path of a sink based on the operating system
+    fn get_config_path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find data directory")?;
+
+        let feeds_dir = proj_dirs.data_dir().join(Self::FEEDS_DIRNAME);
+        fs::create_dir_all(&feeds_dir)?;
+
+        Ok(feeds_dir.join(format!("{}.yaml", file_stem(name))))
+    }
+
+    fn load(name: &str) -> Result<Self, Box<dyn Error>> {
+        let config_path = Self::get_config_path(name)?;
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(config_path)?;
+        let store: FeedStore = serde_yaml::from_str(&contents)?;
+        Ok(store)
+    }
+
+    fn save(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let config_path = Self::get_config_path(name)?;
+        let contents = serde_yaml::to_string(self)?;
+        store::write(config_path, contents)?;
+        Ok(())
+    }
+
+    /// The slug of the feed of a key. Keys that would share one get numbered,
+    /// e.g. `refcount_add(*)` and `refcount_add*` get `refcount_add` and
+    /// `refcount_add-2`
+    fn slug(&mut self, split: Split, key: &str) -> String {
+        if let Some(slug) = self.slugs.get(key) {
+            return slug.clone();
+        }
+
+        let base = split.slug(key);
+        let mut slug = base.clone();
+        let mut suffix = 1;
+        // the path-only feed keeps its slug to itself
+        let reserved = |slug: &str| split == Split::Rule && !key.is_empty() && slug == PATH_SLUG;
+        while reserved(&slug) || self.slugs.values().any(|existing| *existing == slug) {
+            suffix += 1;
+            slug = format!("{}-{}", base, suffix);
+        }
+
+        self.slugs.insert(key.to_owned(), slug.clone());
+        slug
+    }
+}
+
+/// Turns the name of a sink into a file name, one per name. Lowercase letters,
+/// digits and underscores are kept, everything else is written as its code
+/// point, e.g. `my feed` becomes `my-20-feed`
+fn file_stem(name: &str) -> String {
+    let mut stem = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            'a'..='z' | '0'..='9' | '_' => stem.push(c),
+            _ => stem.push_str(&format!("-{:x}-", c as u32)),
+        }
+    }
+    stem
+}
+
+/// Makes sure that every pattern routed to a feed split by rule has a name
+/// for its feed, patterns made of punctuation alone have none
+fn check_slugs(name: &str, notifications: &[Notification]) -> Result<(), Box<dyn Error>> {
+    for notification in notifications {
+        if !notification.routes_to(name) {
+            continue;
+        }
+        let file_patterns = notification
+            .files()
+            .iter()
+            .flatten()
+            .filter_map(|file| file.pattern())
+            .flatten();
+        let mut patterns = notification
+            .patterns()
+            .into_iter()
+            .flatten()
+            .chain(file_patterns);
+        if let Some(pattern) = patterns.find(|pattern| matcher::slug(pattern).is_empty()) {
+            return Err(format!(
+                "The pattern {} of {} has no letters nor digits to name its feed after",
+                pattern,
+                notification.repository().uri()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Escapes text for XML content and attributes
+fn escape(text: &str) -> String {
+    chat::html_escape(text)
+}
+
+fn timestamp(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Publishes the matches as Atom feeds, regenerated at the end of every cycle
+/// that had some
+pub struct FeedNotifier {
+    name: String,
+    path: String,
+    url: Option<String>,
+    max_entries: usize,
+    split: Split,
+    pending: RefCell<Vec<CommitMatch>>,
+    display: Rc<TerminalDisplay>,
+}
+
+impl FeedNotifier {
+    const DEFAULT_MAX_ENTRIES: usize = 50;
+
+    pub fn new(
+        name: String,
+        config: FeedConfig,
+        notifications: &[Notification],
+        display: Rc<TerminalDisplay>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let split = match (
+            config.path.contains(Split::REPOSITORY),
+            config.path.contains(Split::RULE),
+        ) {
+            (false, false) => Split::None,
+            (true, false) => Split::Repository,
+            (false, true) => Split::Rule,
+            (true, true) => {
+                return Err("A feed is split either by repository or by rule, not both".into())
+            }
+        };
+        if split == Split::Rule {
+            check_slugs(&name, notifications)?;
+        }
+        Ok(Self {
+            name,
+            path: config.path,
+            url: config.url,
+            max_entries: config.max_entries.unwrap_or(Self::DEFAULT_MAX_ENTRIES),
+            split,
+            pending: RefCell::new(Vec::new()),
+            display,
+        })
+    }
+
+    /// Renders a single feed, most recent entries first
+    fn render(&self, key: &str, slug: &str, entries: &[FeedEntry]) -> String {
+        let url = self.url.as_ref().map(|url| self.split.expand(url, slug));
+        let id = url.clone().unwrap_or_else(|| {
+            let mut id = format!("urn:vulngrep:{}", file_stem(&self.name));
+            if !slug.is_empty() {
+                id.push(':');
+                id.push_str(slug);
+            }
+            id
+        });
+        let updated = entries
+            .iter()
+            .map(|entry| entry.published)
+            .max()
+            .unwrap_or_else(Utc::now);
+
+        let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        feed.push_str(&format!("  <id>{}</id>\n", escape(&id)));
+        feed.push_str(&format!(
+            "  <title>{}</title>\n",
+            escape(&self.split.title(key))
+        ));
+        feed.push_str(&format!("  <updated>{}</updated>\n", timestamp(updated)));
+        feed.push_str("  <author><name>vulngrep</name></author>\n");
+        feed.push_str(&format!(
+            "  <generator version=\"{}\">vulngrep</generator>\n",
+            env!("CARGO_PKG_VERSION")
+        ));
+        if let Some(url) = &url {
+            feed.push_str(&format!(
+                "  <link rel=\"self\" href=\"{}\"/>\n",
+                escape(url)
+            ));
+        }
+
+        for entry in entries.iter().rev() {
+            let commit_match = &entry.commit_match;
+            let title = format!(
+                "{}@{}: {}",
+                commit_match.repository,
+                commit_match.short_sha(),
+                commit_match.summary
+            );
+            feed.push_str("  <entry>\n");
+            feed.push_str(&format!(
+                "    <id>{}#{}</id>\n",
+                escape(&commit_match.html_url),
+                entry.published.timestamp_millis()
+            ));
+            feed.push_str(&format!("    <title>{}</title>\n", escape(&title)));
+            feed.push_str(&format!(
+                "    <link href=\"{}\"/>\n",
+                escape(&commit_match.html_url)
+            ));
+            feed.push_str(&format!(
+                "    <published>{}</published>\n",
+                timestamp(entry.published)
+            ));
+            feed.push_str(&format!(
+                "    <updated>{}</updated>\n",
+                timestamp(entry.published)
+            ));
+            if let Some(author) = &commit_match.author {
+                feed.push_str(&format!(
+                    "    <author><name>{}</name></author>\n",
+                    escape(author)
+                ));
+            }
+            feed.push_str(&format!(
+                "    <category term=\"{}\"/>\n",
+                commit_match.severity
+            ));
+            feed.push_str(&format!(
+                "    <content type=\"html\">{}</content>\n",
+                escape(&chat::html(commit_match))
+            ));
+            feed.push_str("  </entry>\n");
+        }
+
+        feed.push_str("</feed>\n");
+        feed
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for FeedNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        self.pending.borrow_mut().push(commit_match.clone());
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn Error>> {
+        let pending = self.pending.take();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut store = FeedStore::load(&self.name)?;
+        let mut updated: Vec<String> = Vec::new();
+        let published = Utc::now();
+        for commit_match in pending {
+            for key in self.split.keys(&commit_match) {
+                store.slug(self.split, &key);
+                let entries = store.feeds.entry(key.clone()).or_default();
+                entries.push(FeedEntry {
+                    published,
+                    commit_match: commit_match.clone(),
+                });
+                // only the most recent entries are kept
+                let excess = entries.len().saturating_sub(self.max_entries);
+                entries.drain(..excess);
+                if !updated.contains(&key) {
+                    updated.push(key);
+                }
+            }
+        }
+        store.save(&self.name)?;
+
+        for key in updated {
+            let slug = &store.slugs[&key];
+            let path = self.split.expand(&self.path, slug);
+            if let Some(parent) = Path::new(&path).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            store::write(&path, self.render(&key, slug, &store.feeds[&key]))?;
+            self.display
+                .display_success(&format!("Notifier {} updated {}", self.name, path));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_the_colliding_slugs() {
+        let mut store = FeedStore::default();
+        assert_eq!(store.slug(Split::Rule, "refcount_add(*)"), "refcount_add");
+        assert_eq!(store.slug(Split::Rule, "refcount_add*"), "refcount_add-2");
+        assert_eq!(store.slug(Split::Rule, "refcount_add(*)"), "refcount_add");
+
+        // the path-only feed is named path, whichever comes first
+        assert_eq!(store.slug(Split::Rule, "path"), "path-2");
+        assert_eq!(store.slug(Split::Rule, ""), "path");
+
+        let mut store = FeedStore::default();
+        assert_eq!(store.slug(Split::Repository, "a-b/c"), "a-b-c");
+        assert_eq!(store.slug(Split::Repository, "a/b-c"), "a-b-c-2");
+    }
+
+    #[test]
+    fn gives_every_sink_its_own_store() {
+        assert_eq!(file_stem("feed"), "feed");
+        assert_eq!(file_stem("my feed"), "my-20-feed");
+        assert_eq!(file_stem("my-feed"), "my-2d-feed");
+        assert_ne!(file_stem("Feed"), file_stem("feed"));
+    }
+
+    #[test]
+    fn rejects_the_patterns_without_a_slug() {
+        let notifications: Vec<Notification> = serde_yaml::from_str(
+            r#"
+- repository: { owner: torvalds, name: linux }
+  pattern: ["***"]
+"#,
+        )
+        .unwrap();
+        let config = |path: &str| FeedConfig {
+            path: path.to_owned(),
+            url: None,
+            max_entries: None,
+        };
+        let display = Rc::new(TerminalDisplay::new());
+        assert!(FeedNotifier::new(
+            "feed".to_owned(),
+            config("{rule}.atom"),
+            &notifications,
+            display.clone()
+        )
+        .is_err());
+        assert!(FeedNotifier::new(
+            "feed".to_owned(),
+            config("{repository}.atom"),
+            &notifications,
+            display
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,483 @@
+use crate::config::{AppConfig, Notification, Severity};
+use crate::matcher::CommitMatch;
+use crate::metrics;
+use crate::terminal::TerminalDisplay;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+use throttle::{Throttle, Verdict};
+use tracing::warn;
+
+mod chat;
+mod command;
+mod desktop;
+mod email;
+mod feed;
+mod payload;
+mod report;
+mod template;
+mod terminal;
+mod throttle;
+mod webhook;
+
+pub use chat::{guard_fences, markdown_escape};
+pub use command::{CommandConfig, CommandNotifier};
+pub use desktop::{DesktopConfig, DesktopNotifier};
+pub use email::{EmailConfig, EmailNotifier};
+pub use feed::{FeedConfig, FeedNotifier};
+pub use payload::MatchPayload;
+pub use report::{ReportConfig, ReportNotifier};
+pub use template::TemplateConfig;
+pub use terminal::TerminalNotifier;
+pub use throttle::{Suppressed, ThrottleConfig};
+pub use webhook::{WebhookConfig, WebhookNotifier};
+
+/// A sink that matches are delivered to
+#[async_trait(?Send)]
+pub trait Notifier {
+    /// The name of the sink, as given in the config
+    fn name(&self) -> &str;
+
+    /// Delivers a single match, with all the matching files of the commit
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>>;
+
+    /// Delivers all the matches of a cycle at once, for sinks configured with
+    /// `summary`. Defaults to delivering them one by one
+    async fn notify_summary(&self, matches: &[CommitMatch]) -> Result<(), Box<dyn Error>> {
+        for commit_match in matches {
+            self.notify(commit_match).await?;
+        }
+        Ok(())
+    }
+
+    /// Tells that some matches were dropped because of the rate limits.
+    /// Defaults to doing nothing
+    async fn notify_suppressed(&self, _suppressed: &Suppressed) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Called at the end of every cycle, delivers whatever the sink held back
+    async fn flush(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// The settings of a single sink, as found in the `notifiers` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifierConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    /// Matches of a lower severity are not delivered to the sink
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_severity: Option<Severity>,
+    /// Holds the matches back, and delivers them at once at the end of the cycle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary: Option<bool>,
+    /// Overrides the texts of the notifications
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<TemplateConfig>,
+    #[serde(flatten)]
+    sink: SinkConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    Terminal,
+    Desktop(DesktopConfig),
+    Webhook(WebhookConfig),
+    Email(EmailConfig),
+    #[serde(alias = "exec")]
+    Command(CommandConfig),
+    Report(ReportConfig),
+    Feed(FeedConfig),
+}
+
+impl NotifierConfig {
+    /// The sinks used when the config has no `notifiers` section
+    pub fn defaults() -> Vec<Self> {
+        [
+            SinkConfig::Terminal,
+            SinkConfig::Desktop(DesktopConfig::default()),
+        ]
+        .into_iter()
+        .map(|sink| Self {
+            name: None,
+            enabled: None,
+            min_severity: None,
+            summary: None,
+            template: None,
+            sink,
+        })
+        .collect()
+    }
+
+    /// The name of the sink, defaults to its type
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(match self.sink {
+            SinkConfig::Terminal => "terminal",
+            SinkConfig::Desktop(_) => "desktop",
+            SinkConfig::Webhook(_) => "webhook",
+            SinkConfig::Email(_) => "email",
+            SinkConfig::Command(_) => "command",
+            SinkConfig::Report(_) => "report",
+            SinkConfig::Feed(_) => "feed",
+        })
+    }
+
+    /// Whether the sink is enabled (defaults to true)
+    pub fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    fn build(
+        &self,
+        notifications: &[Notification],
+        display: &Rc<TerminalDisplay>,
+        watching: bool,
+    ) -> Result<Box<dyn Notifier>, Box<dyn Error>> {
+        let name = self.name().to_owned();
+        let template = self.template.as_ref();
+        Ok(match &self.sink {
+            SinkConfig::Terminal => {
+                Box::new(TerminalNotifier::new(name, display.clone(), template)?)
+            }
+            SinkConfig::Desktop(config) => Box::new(DesktopNotifier::new(
+                name,
+                config.clone(),
+                template,
+                watching,
+            )?),
+            SinkConfig::Webhook(config) => {
+                Box::new(WebhookNotifier::new(name, config.clone(), template)?)
+            }
+            SinkConfig::Email(config) => Box::new(EmailNotifier::new(
+                name,
+                config.clone(),
+                template,
+                notifications,
+            )?),
+            SinkConfig::Command(_) if template.is_some() => {
+                return Err("Commands receive the match as JSON, they have no template".into())
+            }
+            SinkConfig::Command(config) => {
+                Box::new(CommandNotifier::new(name, config.clone(), display.clone())?)
+            }
+            SinkConfig::Report(_) | SinkConfig::Feed(_) if template.is_some() => {
+                return Err("Reports and feeds have a fixed layout, they have no template".into())
+            }
+            SinkConfig::Report(config) => {
+                Box::new(ReportNotifier::new(name, config.clone(), display.clone())?)
+            }
+            SinkConfig::Feed(config) => Box::new(FeedNotifier::new(
+                name,
+                config.clone(),
+                notifications,
+                display.clone(),
+            )?),
+        })
+    }
+}
+
+/// Makes sure that every sink has a name of its own, the routes and the held
+/// matches couldn't tell them apart otherwise
+fn check_names(names: &[&str]) -> Result<(), Box<dyn Error>> {
+    for (index, name) in names.iter().enumerate() {
+        if names[..index].contains(name) {
+            return Err(format!(
+                "Several notifiers are named {}, set the `name` of each of them",
+                name
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Makes sure that the rules only route their matches to existing sinks
+fn check_routes(config: &AppConfig, names: &[&str]) -> Result<(), Box<dyn Error>> {
+    for notification in config.notifications() {
+        let file_routes = notification
+            .files()
+            .iter()
+            .flatten()
+            .filter_map(|file| file.notify());
+        for route in notification.notify().into_iter().chain(file_routes) {
+            if let Some(unknown) = route.iter().find(|name| !names.contains(&name.as_str())) {
+                return Err(format!(
+                    "The rules of {} route to the unknown notifier {}",
+                    notification.repository().uri(),
+                    unknown
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An enabled sink, along with the matches it accepts
+struct Sink {
+    notifier: Box<dyn Notifier>,
+    min_severity: Severity,
+    /// Whether the throttle applies, the output of the terminal is never
+    /// held back nor dropped
+    throttled: bool,
+    /// Matches held back until the end of the cycle, in summary mode
+    pending: Option<RefCell<Vec<CommitMatch>>>,
+    /// Matches dropped during the cycle because of the rate limits
+    suppressed: RefCell<Suppressed>,
+}
+
+impl Sink {
+    fn accepts(&self, severity: Severity, notify: Option<&Vec<String>>) -> bool {
+        severity >= self.min_severity
+            && notify.is_none_or(|names| {
+                names
+                    .iter()
+                    .any(|name| name.as_str() == self.notifier.name())
+            })
+    }
+
+    /// The part of a match that is routed to the sink, if any
+    fn select(&self, commit_match: &CommitMatch) -> Option<CommitMatch> {
+        if commit_match.files.is_empty() {
+            return self
+                .accepts(commit_match.severity, commit_match.notify.as_ref())
+                .then(|| commit_match.clone());
+        }
+        commit_match.filter_files(|file| self.accepts(file.severity, file.notify.as_ref()))
+    }
+}
+
+/// Dispatches every match to the enabled sinks its rule routes to, within
+/// the limits of the throttle
+pub struct Notifiers {
+    sinks: Vec<Sink>,
+    throttle: Throttle,
+    display: Rc<TerminalDisplay>,
+}
+
+impl Notifiers {
+    /// Creates the enabled sinks out of the config. `watching` tells whether
+    /// the process keeps running in between the cycles
+    pub fn new(
+        config: &AppConfig,
+        display: Rc<TerminalDisplay>,
+        watching: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let configs = config.notifiers();
+        // disabled sinks are valid routes, their matches are simply dropped
+        let names: Vec<&str> = configs.iter().map(|notifier| notifier.name()).collect();
+        check_names(&names)?;
+        check_routes(config, &names)?;
+
+        let sinks = configs
+            .iter()
+            .filter(|notifier| notifier.enabled())
+            .map(|notifier| {
+                let sink = notifier
+                    .build(config.notifications(), &display, watching)
+                    .map_err(|err| format!("Invalid notifier {}: {}", notifier.name(), err))?;
+                Ok(Sink {
+                    notifier: sink,
+                    min_severity: notifier.min_severity.unwrap_or(Severity::Low),
+                    throttled: !matches!(notifier.sink, SinkConfig::Terminal),
+                    pending: notifier
+                        .summary
+                        .unwrap_or(false)
+                        .then(|| RefCell::new(Vec::new())),
+                    suppressed: RefCell::default(),
+                })
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+        let throttle = Throttle::new(&config.throttle())
+            .map_err(|err| format!("Invalid throttle: {}", err))?;
+
+        Ok(Self {
+            sinks,
+            throttle,
+            display,
+        })
+    }
+
+    /// Delivers a match to every sink its rule routes to. A failing sink is
+    /// reported to the user, but doesn't stop the delivery to the rest of them
+    pub async fn dispatch(&self, commit_match: &CommitMatch) {
+        let selections: Vec<(&Sink, CommitMatch)> = self
+            .sinks
+            .iter()
+            .filter_map(|sink| Some((sink, sink.select(commit_match)?)))
+            .collect();
+        // only the matches that reach a throttled sink count against the limits
+        let verdict = if selections.iter().any(|(sink, _)| sink.throttled) {
+            self.throttle.check(&commit_match.repository)
+        } else {
+            Verdict::Deliver
+        };
+
+        for (sink, selected) in selections {
+            match verdict {
+                Verdict::Hold if sink.throttled => {
+                    self.throttle.hold(sink.notifier.name(), selected);
+                    continue;
+                }
+                Verdict::Suppress if sink.throttled => {
+                    sink.suppressed.borrow_mut().add(&commit_match.repository);
+                    continue;
+                }
+                _ => (),
+            }
+            if let Some(pending) = &sink.pending {
+                pending.borrow_mut().push(selected);
+                continue;
+            }
+            if let Err(err) = sink.notifier.notify(&selected).await {
+                self.report_failure(sink, err);
+            }
+        }
+
+        if verdict == Verdict::Hold {
+            // the match is already recorded, so it only lives on in the throttle
+            self.save_throttle();
+        }
+    }
+
+    /// Delivers the summaries, including the matches held back during the
+    /// quiet hours once they are over, reports the suppressed matches and lets
+    /// every sink deliver what it held back during the cycle
+    pub async fn flush(&self) {
+        let held = self.throttle.take_held();
+
+        for sink in &self.sinks {
+            let mut summary: Vec<CommitMatch> =
+                held.get(sink.notifier.name()).cloned().unwrap_or_default();
+            summary.extend(sink.pending.as_ref().map(RefCell::take).unwrap_or_default());
+            if !summary.is_empty() {
+                if let Err(err) = sink.notifier.notify_summary(&summary).await {
+                    self.report_failure(sink, err);
+                }
+            }
+
+            let suppressed = sink.suppressed.take();
+            if suppressed.count > 0 {
+                if let Err(err) = sink.notifier.notify_suppressed(&suppressed).await {
+                    self.report_failure(sink, err);
+                }
+            }
+
+            if let Err(err) = sink.notifier.flush().await {
+                self.report_failure(sink, err);
+            }
+        }
+
+        self.save_throttle();
+    }
+
+    fn save_throttle(&self) {
+        if let Err(err) = self.throttle.save() {
+            warn!(error = %err, "failed to save the throttle");
+            let err = format!("Failed to save the held matches: {}", err);
+            self.display.display_warning(err.as_str());
+        }
+    }
+
+    fn report_failure(&self, sink: &Sink, err: Box<dyn Error>) {
+        metrics::sink_failure(sink.notifier.name());
+        let err = format!("Notifier {} failed: {}", sink.notifier.name(), err);
+        self.display.display_warning(err.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+    use throttle::tests::throttle;
+
+    /// Keeps the hashes of the matches it receives
+    struct Recorder {
+        name: &'static str,
+        delivered: Rc<RefCell<Vec<String>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+            self.delivered.borrow_mut().push(commit_match.sha.clone());
+            Ok(())
+        }
+    }
+
+    fn sink(
+        name: &'static str,
+        min_severity: Severity,
+        throttled: bool,
+    ) -> (Sink, Rc<RefCell<Vec<String>>>) {
+        let delivered = Rc::default();
+        let sink = Sink {
+            notifier: Box::new(Recorder {
+                name,
+                delivered: Rc::clone(&delivered),
+            }),
+            min_severity,
+            throttled,
+            pending: None,
+            suppressed: RefCell::default(),
+        };
+        (sink, delivered)
+    }
+
+    #[tokio::test]
+    async fn only_counts_the_matches_of_the_throttled_sinks() {
+        let (terminal, on_terminal) = sink("terminal", Severity::Low, false);
+        let (webhook, on_webhook) = sink("webhook", Severity::Critical, true);
+        let notifiers = Notifiers {
+            sinks: vec![terminal, webhook],
+            throttle: throttle(Some(1), None),
+            display: Rc::new(TerminalDisplay::new()),
+        };
+
+        // neither the terminal nor a sink that drops the match uses up the limit
+        notifiers
+            .dispatch(&commit_match("abc123", "mm/slab.c"))
+            .await;
+        notifiers
+            .dispatch(&commit_match("abc456", "mm/slab.c"))
+            .await;
+        assert_eq!(*on_terminal.borrow(), ["abc123", "abc456"]);
+        assert!(on_webhook.borrow().is_empty());
+
+        let mut critical = commit_match("def123", "mm/slab.c");
+        critical.severity = Severity::Critical;
+        critical.files[0].severity = Severity::Critical;
+        notifiers.dispatch(&critical).await;
+        critical.sha = "def456".to_owned();
+        notifiers.dispatch(&critical).await;
+        assert_eq!(*on_webhook.borrow(), ["def123"]);
+        assert_eq!(notifiers.sinks[1].suppressed.borrow().count, 1);
+    }
+
+    #[test]
+    fn rejects_sinks_with_the_same_name() {
+        let configs: Vec<NotifierConfig> = serde_yaml::from_str(
+            "- { type: webhook, url: \"https://a.example.com\" }\n\
+             - { type: webhook, url: \"https://b.example.com\" }",
+        )
+        .unwrap();
+        let names: Vec<&str> = configs.iter().map(|notifier| notifier.name()).collect();
+        let err = check_names(&names).unwrap_err();
+        assert!(err.to_string().contains("`name`"));
+
+        assert!(check_names(&["webhook", "triage", "terminal"]).is_ok());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::config::Severity;
+use crate::matcher::{CommitMatch, FileMatch};
+use serde::Serialize;
+
+/// The JSON document delivered for every match, e.g.
+///
+/// ```json
+/// {
+///   "event": "match",
+///   "repository": "torvalds/linux",
+///   "sha": "5ee7e5fb987ed56d7282606f6fd75bad13f54dd2",
+///   "html_url": "https://github.com/torvalds/linux/commit/5ee7e5fb98...",
+///   "author": "alice",
+///   "severity": "high",
+///   "patterns": ["refcount_add(*)"],
+///   "message_pattern": null,
+///   "files": [
+///     {
+///       "filename": "drivers/phy/phy-core.c",
+///       "additions": 2,
+///       "deletions": 1,
+///       "patterns": ["refcount_add(*)"],
+///       "excerpt": " \tmutex_lock(&phy->mutex);\n+\trefcount_add(1, &phy->ref);"
+///     }
+///   ]
+/// }
+/// ```
+///
+/// `author` is the GitHub login and may be null, `severity` is the one of the
+/// rule (low, medium, high or critical), `message_pattern` is set when
+/// the commit message matched a repository-wide pattern, `patterns` lists every
+/// pattern responsible for the match and `excerpt` holds the matching lines of
+/// the patch with some context (null when the patch isn't available).
+#[derive(Debug, Serialize)]
+pub struct MatchPayload<'a> {
+    pub event: &'static str,
+    pub repository: &'a str,
+    pub sha: &'a str,
+    pub html_url: &'a str,
+    pub author: Option<&'a str>,
+    pub severity: Severity,
+    pub patterns: Vec<&'a str>,
+    pub message_pattern: Option<&'a str>,
+    pub files: Vec<FilePayload<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilePayload<'a> {
+    pub filename: &'a str,
+    pub additions: u64,
+    pub deletions: u64,
+    pub patterns: &'a [String],
+    pub excerpt: Option<&'a str>,
+}
+
+impl<'a> From<&'a CommitMatch> for MatchPayload<'a> {
+    fn from(commit_match: &'a CommitMatch) -> Self {
+        Self {
+            event: "match",
+            repository: &commit_match.repository,
+            sha: &commit_match.sha,
+            html_url: &commit_match.html_url,
+            author: commit_match.author.as_deref(),
+            severity: commit_match.severity,
+            patterns: commit_match.patterns(),
+            message_pattern: commit_match.message_pattern.as_deref(),
+            files: commit_match.files.iter().map(FilePayload::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a FileMatch> for FilePayload<'a> {
+    fn from(file: &'a FileMatch) -> Self {
+        Self {
+            filename: &file.filename,
+            additions: file.additions,
+            deletions: file.deletions,
+            patterns: &file.patterns,
+            excerpt: file.excerpt.as_deref(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use super::Notifier;
+use crate::matcher::CommitMatch;
+use crate::report::{self, ReportFormat};
+use crate::store;
+use crate::terminal::TerminalDisplay;
+use async_trait::async_trait;
+use chrono::format::{Item, StrftimeItems};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportConfig {
+    /// Where the report is written, `strftime` placeholders such as `%Y-%m-%d`
+    /// are replaced with the local time at the end of the cycle
+    path: String,
+    /// Defaults to html for paths ending with `.html`, markdown otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<ReportFormat>,
+}
+
+/// Writes a Markdown or HTML report of the matches at the end of every cycle
+/// that had some
+pub struct ReportNotifier {
+    name: String,
+    path: String,
+    format: ReportFormat,
+    pending: RefCell<Vec<CommitMatch>>,
+    display: Rc<TerminalDisplay>,
+}
+
+impl ReportNotifier {
+    pub fn new(
+        name: String,
+        config: ReportConfig,
+        display: Rc<TerminalDisplay>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if StrftimeItems::new(&config.path).any(|item| item == Item::Error) {
+            return Err(format!("Invalid placeholder in the report path {}", config.path).into());
+        }
+        let format = config.format.unwrap_or(if config.path.ends_with(".html") {
+            ReportFormat::Html
+        } else {
+            ReportFormat::Markdown
+        });
+        Ok(Self {
+            name,
+            path: config.path,
+            format,
+            pending: RefCell::new(Vec::new()),
+            display,
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for ReportNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        self.pending.borrow_mut().push(commit_match.clone());
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn Error>> {
+        let pending = self.pending.take();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let now = Local::now();
+        let mut path = String::new();
+        write!(path, "{}", now.format(&self.path))?;
+        let title = format!("Vulngrep report of {}", now.format("%Y-%m-%d %H:%M"));
+        let contents = report::render(self.format, &title, &pending)?;
+
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        store::write(&path, contents)?;
+        self.display.display_success(&format!(
+            "Notifier {} wrote {} matching commits to {}",
+            self.name,
+            pending.len(),
+            path
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+use super::chat;
+use super::payload::FilePayload;
+use crate::config::Severity;
+use crate::matcher::{CommitMatch, FileMatch};
+use minijinja::{AutoEscape, Environment, Value};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// User-defined texts of a notifier, as found in its `template` config section.
+/// Templates use the Jinja syntax, see [`Context`] and [`SummaryContext`] for
+/// the placeholders
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    /// Popup summary, email subject, first lines of the terminal output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// Popup body, plain-text email body, chat message, whole terminal output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    /// HTML email body, escaped automatically
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    /// Terminal lines of every matching file, followed by its diff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// Popup summary and email subject of the summaries of several matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary_title: Option<String>,
+    /// Popup body of the summaries of several matches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary_body: Option<String>,
+}
+
+/// The default texts of a notifier, for the templates the user didn't define
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Defaults {
+    pub title: Option<&'static str>,
+    pub body: Option<&'static str>,
+    pub file: Option<&'static str>,
+    pub summary_title: Option<&'static str>,
+    pub summary_body: Option<&'static str>,
+}
+
+/// How the values are escaped, for the texts that end up in a markup language
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    Slack,
+    Markdown,
+    Html,
+}
+
+/// The placeholders available to the templates
+#[derive(Debug, Serialize)]
+struct Context<'a> {
+    repository: &'a str,
+    sha: &'a str,
+    short_sha: &'a str,
+    url: &'a str,
+    author: &'a str,
+    /// The first line of the commit message
+    summary: &'a str,
+    severity: Severity,
+    message_pattern: Option<&'a str>,
+    patterns: Vec<&'a str>,
+    files: Vec<FilePayload<'a>>,
+    /// The first matching file, handy for commits that only have one. The
+    /// file being printed, for the `file` template
+    file: Option<FilePayload<'a>>,
+    /// Total of the matching files
+    additions: u64,
+    deletions: u64,
+}
+
+impl<'a> From<&'a CommitMatch> for Context<'a> {
+    fn from(commit_match: &'a CommitMatch) -> Self {
+        Self {
+            repository: &commit_match.repository,
+            sha: &commit_match.sha,
+            short_sha: commit_match.short_sha(),
+            url: &commit_match.html_url,
+            author: commit_match.author.as_deref().unwrap_or("unknown"),
+            summary: &commit_match.summary,
+            severity: commit_match.severity,
+            message_pattern: commit_match.message_pattern.as_deref(),
+            patterns: commit_match.patterns(),
+            files: commit_match.files.iter().map(FilePayload::from).collect(),
+            file: commit_match.files.first().map(FilePayload::from),
+            additions: commit_match.files.iter().map(|file| file.additions).sum(),
+            deletions: commit_match.files.iter().map(|file| file.deletions).sum(),
+        }
+    }
+}
+
+/// The placeholders available to the summary templates
+#[derive(Debug, Serialize)]
+struct SummaryContext<'a> {
+    count: usize,
+    /// Every match, with the same placeholders as a single one
+    matches: Vec<Context<'a>>,
+    /// The repositories of the matches, without duplicates
+    repositories: Vec<&'a str>,
+}
+
+impl<'a> SummaryContext<'a> {
+    fn new(matches: impl IntoIterator<Item = &'a CommitMatch>) -> Self {
+        let mut contexts = Vec::new();
+        let mut repositories: Vec<&str> = Vec::new();
+        for commit_match in matches {
+            if !repositories.contains(&commit_match.repository.as_str()) {
+                repositories.push(&commit_match.repository);
+            }
+            contexts.push(Context::from(commit_match));
+        }
+        Self {
+            count: contexts.len(),
+            matches: contexts,
+            repositories,
+        }
+    }
+}
+
+/// The compiled templates of a notifier
+pub struct Template {
+    env: Environment<'static>,
+}
+
+impl Template {
+    const TITLE: &'static str = "title";
+    const BODY: &'static str = "body";
+    const FILE: &'static str = "file";
+    const SUMMARY_TITLE: &'static str = "summary_title";
+    const SUMMARY_BODY: &'static str = "summary_body";
+    // the extension turns auto-escaping on
+    const HTML: &'static str = "body.html";
+    /// The body once more, with the values escaped for a markup language
+    const BODY_SLACK: &'static str = "body.slack";
+    const BODY_MARKDOWN: &'static str = "body.md";
+    const BODY_HTML: &'static str = "body.text.html";
+
+    /// Compiles the user-defined templates, which override the defaults of
+    /// the notifier
+    pub fn new(
+        config: Option<&TemplateConfig>,
+        defaults: Defaults,
+    ) -> Result<Self, Box<dyn Error>> {
+        let config = config.cloned().unwrap_or_default();
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_auto_escape_callback(|name| match name.rsplit_once('.') {
+            Some((_, "html")) => AutoEscape::Html,
+            Some((_, "slack")) => AutoEscape::Custom("slack"),
+            Some((_, "md")) => AutoEscape::Custom("markdown"),
+            _ => AutoEscape::None,
+        });
+        env.set_formatter(|out, state, value| {
+            let escape = match state.auto_escape() {
+                AutoEscape::Custom("slack") => chat::slack_escape,
+                AutoEscape::Custom(_) => chat::markdown_escape,
+                _ => return minijinja::escape_formatter(out, state, value),
+            };
+            if value.is_undefined() {
+                return Ok(());
+            }
+            let text = value.to_string();
+            if value.is_safe() {
+                out.write_str(&text)?;
+            } else {
+                out.write_str(&escape(&text))?;
+            }
+            Ok(())
+        });
+
+        let body = config.body.as_deref().or(defaults.body).map(str::to_owned);
+        let templates = [
+            (Self::TITLE, config.title, defaults.title),
+            (Self::BODY, config.body, defaults.body),
+            (Self::HTML, config.html, None),
+            (Self::FILE, config.file, defaults.file),
+            (
+                Self::SUMMARY_TITLE,
+                config.summary_title,
+                defaults.summary_title,
+            ),
+            (
+                Self::SUMMARY_BODY,
+                config.summary_body,
+                defaults.summary_body,
+            ),
+            (Self::BODY_SLACK, body.clone(), None),
+            (Self::BODY_MARKDOWN, body.clone(), None),
+            (Self::BODY_HTML, body, None),
+        ];
+        for (name, source, default) in templates {
+            match (source, default) {
+                (Some(source), _) => env.add_template_owned(name, source)?,
+                (None, Some(default)) => env.add_template(name, default)?,
+                (None, None) => (),
+            }
+        }
+        Ok(Self { env })
+    }
+
+    fn render(&self, name: &str, context: Value) -> Result<Option<String>, Box<dyn Error>> {
+        let Ok(template) = self.env.get_template(name) else {
+            return Ok(None);
+        };
+        Ok(Some(template.render(context)?))
+    }
+
+    fn render_match(
+        &self,
+        name: &str,
+        commit_match: &CommitMatch,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        self.render(name, Value::from_serialize(Context::from(commit_match)))
+    }
+
+    pub fn title(&self, commit_match: &CommitMatch) -> Result<Option<String>, Box<dyn Error>> {
+        self.render_match(Self::TITLE, commit_match)
+    }
+
+    pub fn body(&self, commit_match: &CommitMatch) -> Result<Option<String>, Box<dyn Error>> {
+        self.render_match(Self::BODY, commit_match)
+    }
+
+    /// The body, with the values escaped so that they show up as they are
+    pub fn escaped_body(
+        &self,
+        commit_match: &CommitMatch,
+        escape: Escape,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let name = match escape {
+            Escape::Slack => Self::BODY_SLACK,
+            Escape::Markdown => Self::BODY_MARKDOWN,
+            Escape::Html => Self::BODY_HTML,
+        };
+        self.render_match(name, commit_match)
+    }
+
+    pub fn html(&self, commit_match: &CommitMatch) -> Result<Option<String>, Box<dyn Error>> {
+        self.render_match(Self::HTML, commit_match)
+    }
+
+    /// The lines about one of the files of a match
+    pub fn file(
+        &self,
+        commit_match: &CommitMatch,
+        file: &FileMatch,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let mut context = Context::from(commit_match);
+        context.file = Some(FilePayload::from(file));
+        self.render(Self::FILE, Value::from_serialize(context))
+    }
+
+    pub fn summary_title<'a>(
+        &self,
+        matches: impl IntoIterator<Item = &'a CommitMatch>,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let context = SummaryContext::new(matches);
+        self.render(Self::SUMMARY_TITLE, Value::from_serialize(context))
+    }
+
+    pub fn summary_body<'a>(
+        &self,
+        matches: impl IntoIterator<Item = &'a CommitMatch>,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let context = SummaryContext::new(matches);
+        self.render(Self::SUMMARY_BODY, Value::from_serialize(context))
+    }
+
+    /// Makes a filter available to the templates, e.g. to style the terminal output
+    pub fn add_filter<F, Rv, Args>(&mut self, name: &'static str, filter: F)
+    where
+        F: minijinja::functions::Function<Rv, Args>,
+        Rv: minijinja::value::FunctionResult,
+        Args: for<'a> minijinja::value::FunctionArgs<'a>,
+    {
+        self.env.add_filter(name, filter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+
+    fn template(yaml: &str) -> Template {
+        let config: TemplateConfig = serde_yaml::from_str(yaml).unwrap();
+        Template::new(Some(&config), Defaults::default()).unwrap()
+    }
+
+    #[test]
+    fn escapes_the_values_only() {
+        let template = template(r#"body: "<{{ url }}|{{ summary }}> *{{ author }}*""#);
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        commit_match.summary = "Fix <b> & *stars*".to_owned();
+        commit_match.html_url = "https://example.com".to_owned();
+
+        let slack = template.escaped_body(&commit_match, Escape::Slack).unwrap();
+        assert_eq!(
+            slack.as_deref(),
+            Some("<https://example.com|Fix &lt;b&gt; &amp; *stars*> *alice*")
+        );
+        let markdown = template
+            .escaped_body(&commit_match, Escape::Markdown)
+            .unwrap();
+        assert_eq!(
+            markdown.as_deref(),
+            Some(r"<https://example.com|Fix \<b\> & \*stars\*> *alice*")
+        );
+        let html = template.escaped_body(&commit_match, Escape::Html).unwrap();
+        assert_eq!(
+            html.as_deref(),
+            Some("<https:&#x2f;&#x2f;example.com|Fix &lt;b&gt; &amp; *stars*> *alice*")
+        );
+        let plain = template.body(&commit_match).unwrap();
+        assert_eq!(
+            plain.as_deref(),
+            Some("<https://example.com|Fix <b> & *stars*> *alice*")
+        );
+    }
+
+    #[test]
+    fn leaves_safe_values_alone() {
+        let template = template(r#"body: "{{ summary | safe }}""#);
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        commit_match.summary = "*bold*".to_owned();
+        let markdown = template
+            .escaped_body(&commit_match, Escape::Markdown)
+            .unwrap();
+        assert_eq!(markdown.as_deref(), Some("*bold*"));
+    }
+
+    #[test]
+    fn renders_the_summaries() {
+        let template = template(
+            r#"summary_title: "{{ count }} in {{ repositories | join(', ') }}: {% for match in matches %}{{ match.short_sha }} {% endfor %}""#,
+        );
+        let mut other = commit_match("def4567890", "src/lib.rs");
+        other.repository = "rust-lang/rust".to_owned();
+        let matches = [commit_match("abc1234567", "mm/slab.c"), other];
+        assert_eq!(
+            template.summary_title(&matches).unwrap().as_deref(),
+            Some("2 in torvalds/linux, rust-lang/rust: abc1234 def4567 ")
+        );
+        assert_eq!(template.summary_body(&matches).unwrap(), None);
+    }
+
+    #[test]
+    fn renders_a_file_at_a_time() {
+        let template = template(r#"file: "{{ file.filename }} +{{ file.additions }}""#);
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        let mut second = commit_match.files[0].clone();
+        second.filename = "mm/slub.c".to_owned();
+        second.additions = 7;
+        commit_match.files.push(second);
+
+        let file = &commit_match.files[1];
+        assert_eq!(
+            template.file(&commit_match, file).unwrap().as_deref(),
+            Some("mm/slub.c +7")
+        );
+    }
+}
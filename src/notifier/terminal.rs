@@ -0,0 +1,99 @@
+use super::template::{Defaults, Template, TemplateConfig};
+use super::{Notifier, Suppressed};
+use crate::diff;
+use crate::matcher::CommitMatch;
+use crate::terminal::TerminalDisplay;
+use async_trait::async_trait;
+use console::style;
+use minijinja::Value;
+use std::error::Error;
+use std::rc::Rc;
+
+/// Prints the matches in the terminal, unless they are written as JSON with `--output`
+pub struct TerminalNotifier {
+    name: String,
+    display: Rc<TerminalDisplay>,
+    template: Template,
+}
+
+const DEFAULT_TITLE: &str = r#"Commit SHA: {{ sha | link(url) }}
+{% if message_pattern %}
+Pattern matched: {{ message_pattern | bold }}
+{% endif %}
+"#;
+const DEFAULT_FILE: &str = r#"File: {{ file.filename | bold }}, Additions: {{ file.additions | green | underline }}, Deletions: {{ file.deletions | red | underline }}
+{% for pattern in file.patterns %}
+Pattern matched: {{ pattern | bold }}
+{% endfor %}
+"#;
+
+impl TerminalNotifier {
+    pub fn new(
+        name: String,
+        display: Rc<TerminalDisplay>,
+        template: Option<&TemplateConfig>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut template = Template::new(
+            template,
+            Defaults {
+                title: Some(DEFAULT_TITLE),
+                file: Some(DEFAULT_FILE),
+                ..Defaults::default()
+            },
+        )?;
+        template.add_filter("link", display.link_filter());
+        template.add_filter("bold", |text: Value| style(text.to_string()).bold().to_string());
+        template.add_filter("underline", |text: Value| {
+            style(text.to_string()).underlined().to_string()
+        });
+        template.add_filter("green", |text: Value| style(text.to_string()).green().to_string());
+        template.add_filter("red", |text: Value| style(text.to_string()).red().to_string());
+        Ok(Self {
+            name,
+            display,
+            template,
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for TerminalNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        // the watcher writes the matches to the structured output itself
+        if self.display.is_structured() {
+            return Ok(());
+        }
+
+        // a body replaces the whole output
+        if let Some(body) = self.template.body(commit_match)? {
+            self.display.templated_match(&body);
+            return Ok(());
+        }
+
+        if let Some(title) = self.template.title(commit_match)? {
+            self.display.match_title(&title);
+        }
+
+        for file in &commit_match.files {
+            if let Some(text) = self.template.file(commit_match, file)? {
+                self.display.match_file(&text);
+            }
+
+            // the excerpt stands in for the patch of matches loaded from disk
+            if let Some(patch) = file.patch.as_deref().or(file.excerpt.as_deref()) {
+                let hunks = diff::hunks(patch, &file.patterns, self.display.diff_context());
+                self.display.diff_hunks(&hunks, &file.patterns);
+            }
+        }
+        Ok(())
+    }
+
+    async fn notify_suppressed(&self, suppressed: &Suppressed) -> Result<(), Box<dyn Error>> {
+        self.display.display_warning(&suppressed.to_string());
+        Ok(())
+    }
+}
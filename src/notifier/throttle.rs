@@ -0,0 +1,335 @@
+use crate::matcher::CommitMatch;
+use crate::store;
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, Utc};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Limits on the delivery of the matches, as found in the `throttle` config section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThrottleConfig {
+    /// Local time range, e.g. `22:00-07:00`, during which matches are held
+    /// back and delivered later as a summary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quiet_hours: Option<String>,
+    /// Upper bound on the matches delivered per hour, across all repositories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_per_hour: Option<usize>,
+    /// Upper bound on the matches delivered per hour, for every repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_per_repository_per_hour: Option<usize>,
+}
+
+/// What to do with a match
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Deliver,
+    /// Deliver it once the quiet hours are over
+    Hold,
+    /// Drop it, it's over the rate limits
+    Suppress,
+}
+
+/// The matches dropped because of the rate limits
+#[derive(Debug, Default)]
+pub struct Suppressed {
+    pub count: usize,
+    /// How many matches of every repository were dropped
+    pub repositories: BTreeMap<String, usize>,
+}
+
+impl Suppressed {
+    pub fn add(&mut self, repository: &str) {
+        self.count += 1;
+        *self.repositories.entry(repository.to_owned()).or_default() += 1;
+    }
+}
+
+impl fmt::Display for Suppressed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let repositories: Vec<String> = self
+            .repositories
+            .iter()
+            .map(|(repository, count)| format!("{} in {}", count, repository))
+            .collect();
+        let noun = if self.count == 1 { "match" } else { "matches" };
+        write!(
+            f,
+            "{} more {} suppressed ({})",
+            self.count,
+            noun,
+            repositories.join(", ")
+        )
+    }
+}
+
+/// A daily range of local time, possibly wrapping around midnight
+#[derive(Debug, Clone, Copy)]
+struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    fn parse(range: &str) -> Result<Self, Box<dyn Error>> {
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid quiet hours {}, expected e.g. 22:00-07:00", range))?;
+        Ok(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M")?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M")?,
+        })
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Keeps track of the deliveries of the last hour
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Window {
+    deliveries: VecDeque<DateTime<Utc>>,
+}
+
+impl Window {
+    const LENGTH: TimeDelta = TimeDelta::hours(1);
+
+    fn count(&mut self, now: DateTime<Utc>) -> usize {
+        while self
+            .deliveries
+            .front()
+            .is_some_and(|delivery| now - *delivery >= Self::LENGTH)
+        {
+            self.deliveries.pop_front();
+        }
+        self.deliveries.len()
+    }
+}
+
+/// What the throttle keeps across runs, so that neither the held matches nor
+/// the deliveries of the last hour are forgotten when the watcher restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ThrottleStore {
+    /// Matches held back during the quiet hours, keyed by the name of the
+    /// sink they are routed to
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    held: BTreeMap<String, Vec<CommitMatch>>,
+    #[serde(default)]
+    global: Window,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    repositories: BTreeMap<String, Window>,
+    /// Whether anything changed since the store was last saved
+    #[serde(skip)]
+    unsaved: bool,
+}
+
+impl ThrottleStore {
+    const CONFIG_FILENAME: &'static str = "throttle.yaml";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the store file path based on the operating system
+    fn get_config_path() -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find config directory")?;
+
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir)?;
+
+        Ok(data_dir.join(Self::CONFIG_FILENAME))
+    }
+
+    /// Load the throttle state from the default location
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(config_path)?;
+        let store: ThrottleStore = serde_yaml::from_str(&contents)?;
+        Ok(store)
+    }
+
+    /// Save the throttle state to the default location, if anything changed
+    fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.unsaved {
+            return Ok(());
+        }
+
+        // the deliveries older than an hour don't count anymore
+        let now = Utc::now();
+        self.global.count(now);
+        self.repositories.retain(|_, window| window.count(now) > 0);
+
+        let config_path = Self::get_config_path()?;
+        let contents = serde_yaml::to_string(self)?;
+        store::write(config_path, contents)?;
+        self.unsaved = false;
+        Ok(())
+    }
+}
+
+/// Decides whether matches are delivered right away, held back or dropped
+pub struct Throttle {
+    quiet_hours: Option<QuietHours>,
+    max_per_hour: Option<usize>,
+    max_per_repository: Option<usize>,
+    store: RefCell<ThrottleStore>,
+}
+
+impl Throttle {
+    pub fn new(config: &ThrottleConfig) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            quiet_hours: config
+                .quiet_hours
+                .as_deref()
+                .map(QuietHours::parse)
+                .transpose()?,
+            max_per_hour: config.max_per_hour,
+            max_per_repository: config.max_per_repository_per_hour,
+            store: RefCell::new(ThrottleStore::load()?),
+        })
+    }
+
+    /// Whether it's quiet hours right now
+    pub fn is_quiet(&self) -> bool {
+        self.quiet_hours
+            .is_some_and(|quiet_hours| quiet_hours.contains(Local::now().time()))
+    }
+
+    /// Decides the fate of a match of a repository, and counts it as delivered
+    /// if it gets delivered
+    pub fn check(&self, repository: &str) -> Verdict {
+        if self.is_quiet() {
+            return Verdict::Hold;
+        }
+
+        if self.max_per_hour.is_none() && self.max_per_repository.is_none() {
+            return Verdict::Deliver;
+        }
+
+        let now = Utc::now();
+        let mut store = self.store.borrow_mut();
+        store.unsaved = true;
+        let ThrottleStore {
+            global,
+            repositories,
+            ..
+        } = &mut *store;
+        let window = repositories.entry(repository.to_owned()).or_default();
+
+        let over_global = self
+            .max_per_hour
+            .is_some_and(|max| global.count(now) >= max);
+        let over_repository = self
+            .max_per_repository
+            .is_some_and(|max| window.count(now) >= max);
+        if over_global || over_repository {
+            return Verdict::Suppress;
+        }
+
+        global.deliveries.push_back(now);
+        window.deliveries.push_back(now);
+        Verdict::Deliver
+    }
+
+    /// Holds back the part of a match that is routed to a sink
+    pub fn hold(&self, sink: &str, commit_match: CommitMatch) {
+        let mut store = self.store.borrow_mut();
+        store
+            .held
+            .entry(sink.to_owned())
+            .or_default()
+            .push(commit_match);
+        store.unsaved = true;
+    }
+
+    /// The matches held back for every sink, once the quiet hours are over
+    pub fn take_held(&self) -> BTreeMap<String, Vec<CommitMatch>> {
+        if self.is_quiet() {
+            return BTreeMap::new();
+        }
+        let mut store = self.store.borrow_mut();
+        let held = std::mem::take(&mut store.held);
+        store.unsaved |= !held.is_empty();
+        held
+    }
+
+    /// Writes the held matches and the deliveries of the last hour to disk
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.store.borrow_mut().save()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A throttle without quiet hours, that starts afresh
+    pub fn throttle(max_per_hour: Option<usize>, max_per_repository: Option<usize>) -> Throttle {
+        Throttle {
+            quiet_hours: None,
+            max_per_hour,
+            max_per_repository,
+            store: RefCell::default(),
+        }
+    }
+
+    #[test]
+    fn parses_quiet_hours() {
+        let quiet_hours = QuietHours::parse("22:00-07:00").unwrap();
+        let time = |time| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        assert!(quiet_hours.contains(time("23:30")));
+        assert!(quiet_hours.contains(time("06:59")));
+        assert!(!quiet_hours.contains(time("07:00")));
+        assert!(!quiet_hours.contains(time("12:00")));
+        assert!(QuietHours::parse("22:00").is_err());
+    }
+
+    #[test]
+    fn suppresses_the_matches_over_the_limits() {
+        let throttle = throttle(Some(3), Some(2));
+        assert_eq!(throttle.check("torvalds/linux"), Verdict::Deliver);
+        assert_eq!(throttle.check("torvalds/linux"), Verdict::Deliver);
+        assert_eq!(throttle.check("torvalds/linux"), Verdict::Suppress);
+        assert_eq!(throttle.check("rust-lang/rust"), Verdict::Deliver);
+        assert_eq!(throttle.check("rust-lang/rust"), Verdict::Suppress);
+    }
+
+    #[test]
+    fn forgets_the_deliveries_after_an_hour() {
+        let now = Utc::now();
+        let mut window = Window::default();
+        window.deliveries.push_back(now - TimeDelta::minutes(61));
+        window.deliveries.push_back(now - TimeDelta::minutes(59));
+        assert_eq!(window.count(now), 1);
+    }
+
+    #[test]
+    fn keeps_the_held_matches_across_runs() {
+        let throttle = throttle(None, None);
+        let commit_match = crate::matcher::tests::commit_match("abc123", "mm/slab.c");
+        throttle.hold("email", commit_match);
+
+        let contents = serde_yaml::to_string(&*throttle.store.borrow()).unwrap();
+        let store: ThrottleStore = serde_yaml::from_str(&contents).unwrap();
+        assert_eq!(store.held["email"][0].sha, "abc123");
+
+        let held = throttle.take_held();
+        assert_eq!(held["email"].len(), 1);
+        assert!(throttle.store.borrow().held.is_empty());
+    }
+}
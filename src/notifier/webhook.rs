@@ -0,0 +1,347 @@
+use super::chat::{self, ChatFormat};
+use super::template::{Defaults, Template, TemplateConfig};
+use super::{Notifier, Suppressed};
+use crate::config::parse_duration;
+use crate::matcher::CommitMatch;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    url: String,
+    /// Shape of the body, either the vulngrep payload or a chat message
+    #[serde(default)]
+    format: ChatFormat,
+    /// Extra headers sent along with every request, e.g. `Authorization`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    /// Key used to sign the body, see [`WebhookNotifier::SIGNATURE_HEADER`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retries: Option<u32>,
+    /// Delay before the first retry, doubled on every following one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backoff: Option<String>,
+}
+
+#[derive(Debug, Error)]
+enum WebhookError {
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Server responded with {0}")]
+    Status(StatusCode),
+}
+
+impl WebhookError {
+    /// Whether the request may succeed if sent again later
+    fn is_transient(&self) -> bool {
+        match self {
+            WebhookError::Request(err) => !err.is_builder(),
+            WebhookError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+/// POSTs every match as JSON to a URL, either as a
+/// [`MatchPayload`](super::payload::MatchPayload) or as a chat message
+pub struct WebhookNotifier {
+    name: String,
+    url: reqwest::Url,
+    format: ChatFormat,
+    /// Replaces the rich chat message with a plain one when it has a body
+    template: Template,
+    secret: Option<String>,
+    retries: u32,
+    backoff: Duration,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    /// Hex encoded HMAC-SHA256 of the body, keyed with the secret
+    pub const SIGNATURE_HEADER: &'static str = "X-Vulngrep-Signature-256";
+    const DEFAULT_TIMEOUT: &'static str = "10s";
+    const DEFAULT_RETRIES: u32 = 3;
+    const DEFAULT_BACKOFF: &'static str = "1s";
+    /// The delay between two retries never grows past this
+    const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+    pub fn new(
+        name: String,
+        config: WebhookConfig,
+        template: Option<&TemplateConfig>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if template.is_some() && config.format == ChatFormat::Json {
+            return Err("Templates only apply to the chat formats".into());
+        }
+
+        let mut headers = HeaderMap::new();
+        for (header, value) in &config.headers {
+            headers.insert(
+                HeaderName::from_bytes(header.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let timeout = parse_duration(config.timeout.as_deref().unwrap_or(Self::DEFAULT_TIMEOUT))?;
+        if timeout == 0 {
+            // reqwest would fail every request right away
+            return Err("The webhook timeout must be at least 1s".into());
+        }
+        let backoff = parse_duration(config.backoff.as_deref().unwrap_or(Self::DEFAULT_BACKOFF))?;
+
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("vulngrep/", env!("CARGO_PKG_VERSION")))
+            .default_headers(headers)
+            .timeout(Duration::from_secs(timeout))
+            .build()?;
+
+        Ok(Self {
+            name,
+            url: reqwest::Url::parse(&config.url)?,
+            format: config.format,
+            template: Template::new(template, Defaults::default())?,
+            secret: config.secret,
+            retries: config.retries.unwrap_or(Self::DEFAULT_RETRIES),
+            backoff: Duration::from_secs(backoff).min(Self::MAX_BACKOFF),
+            client,
+        })
+    }
+
+    /// Sends the body once
+    async fn send(&self, body: &[u8]) -> Result<(), WebhookError> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+
+        if let Some(secret) = &self.secret {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                .expect("HMAC accepts keys of any size");
+            mac.update(body);
+            let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+            request = request.header(Self::SIGNATURE_HEADER, signature);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(WebhookError::Status(response.status()));
+        }
+        Ok(())
+    }
+
+    /// Sends the body, retrying with an exponential backoff on transient errors
+    async fn post(&self, body: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.send(body).await {
+                Ok(()) => return Ok(()),
+                Err(err) if err.is_transient() && attempt < self.retries => {
+                    tokio::time::sleep(delay).await;
+                    delay = delay.saturating_mul(2).min(Self::MAX_BACKOFF);
+                    attempt += 1;
+                }
+                Err(err) if attempt > 0 => {
+                    return Err(format!("{} (after {} retries)", err, attempt).into())
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        let message = match chat::render_template(self.format, &self.template, commit_match)? {
+            Some(message) => message,
+            None => chat::render(self.format, commit_match),
+        };
+        self.post(&serde_json::to_vec(&message)?).await
+    }
+
+    async fn notify_suppressed(&self, suppressed: &Suppressed) -> Result<(), Box<dyn Error>> {
+        let body = serde_json::to_vec(&chat::render_suppressed(self.format, suppressed))?;
+        self.post(&body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// A request as received by the local listener
+    struct Request {
+        headers: BTreeMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// Serves one response per connection, with the given statuses in order,
+    /// and hands over the requests it received
+    async fn listen(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut headers = BTreeMap::new();
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                loop {
+                    line.clear();
+                    stream.read_line(&mut line).await.unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.insert(name.to_ascii_lowercase(), value.to_owned());
+                }
+                let length = headers["content-length"].parse().unwrap();
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} Whatever\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                sender.send(Request { headers, body }).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn config(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            format: ChatFormat::Json,
+            headers: BTreeMap::new(),
+            secret: None,
+            timeout: None,
+            retries: Some(2),
+            backoff: Some("0s".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_the_payload() {
+        let (url, mut requests) = listen(vec![200]).await;
+        let mut config = config(url);
+        config
+            .headers
+            .insert("Authorization".to_owned(), "Bearer token".to_owned());
+        let notifier = WebhookNotifier::new("hook".to_owned(), config, None).unwrap();
+
+        notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(request.headers["authorization"], "Bearer token");
+        assert!(!request.headers.contains_key("x-vulngrep-signature-256"));
+        let payload: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(payload["event"], "match");
+        assert_eq!(payload["repository"], "torvalds/linux");
+        assert_eq!(payload["sha"], "abc123");
+        assert_eq!(payload["author"], "alice");
+        assert_eq!(payload["severity"], "high");
+        assert_eq!(payload["patterns"], serde_json::json!(["UAF"]));
+        assert_eq!(payload["message_pattern"], serde_json::Value::Null);
+        assert_eq!(payload["files"][0]["filename"], "mm/slab.c");
+        assert_eq!(payload["files"][0]["additions"], 1);
+    }
+
+    #[tokio::test]
+    async fn signs_the_body() {
+        let (url, mut requests) = listen(vec![204]).await;
+        let mut config = config(url);
+        config.secret = Some("It's a secret to everybody".to_owned());
+        let notifier = WebhookNotifier::new("hook".to_owned(), config, None).unwrap();
+
+        notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"It's a secret to everybody").unwrap();
+        mac.update(&request.body);
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(request.headers["x-vulngrep-signature-256"], expected);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let (url, mut requests) = listen(vec![503, 429, 200]).await;
+        let notifier = WebhookNotifier::new("hook".to_owned(), config(url), None).unwrap();
+
+        notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            let request = requests.recv().await.unwrap();
+            assert!(!request.body.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retries() {
+        let (url, _requests) = listen(vec![500, 500, 500]).await;
+        let notifier = WebhookNotifier::new("hook".to_owned(), config(url), None).unwrap();
+
+        let err = notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Server responded with 500 Internal Server Error (after 2 retries)"
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, mut requests) = listen(vec![404]).await;
+        let notifier = WebhookNotifier::new("hook".to_owned(), config(url), None).unwrap();
+
+        let err = notifier
+            .notify(&commit_match("abc123", "mm/slab.c"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Server responded with 404 Not Found");
+        requests.recv().await.unwrap();
+    }
+
+    #[test]
+    fn rejects_a_zero_timeout() {
+        let mut config = config("http://127.0.0.1/hook".to_owned());
+        config.timeout = Some("0s".to_owned());
+        assert!(WebhookNotifier::new("hook".to_owned(), config, None).is_err());
+    }
+}
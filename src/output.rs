@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::error::Error;
+use std::str::FromStr;
+
+/// How the progress of vulngrep is written to the standard output
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Coloured text with clickable links, meant for a terminal
+    #[default]
+    Human,
+    /// One compact JSON document per line and per event, so that the
+    /// output can be parsed as a stream
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(Self::Human),
+            // JSON is written one line at a time too, pretty documents can't
+            // be told apart in a stream
+            "json" | "ndjson" => Ok(Self::Ndjson),
+            _ => Err(format!(
+                "Invalid output format {}, expected human, json or ndjson",
+                format
+            )
+            .into()),
+        }
+    }
+}
+
+/// The events of the structured output, matches are written as a
+/// [`MatchPayload`](crate::notifier::MatchPayload) instead
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A repository is being checked for new commits
+    Inspect {
+        repository: &'a str,
+    },
+    /// A repository seen for the first time, its commits will be checked from now on
+    RepositoryAdded {
+        repository: &'a str,
+    },
+    /// Past commits are being downloaded
+    Backfill {
+        since: DateTime<Utc>,
+    },
+    ScanSummary {
+        commits: usize,
+        matching_commits: usize,
+    },
+    /// The cached commits of a repository have new matches
+    Rescan {
+        repository: &'a str,
+    },
+    RescanSummary {
+        commits: usize,
+        new_matches: usize,
+    },
+    History {
+        repository: &'a str,
+        sha: &'a str,
+    },
+    SearchResult {
+        repository: &'a str,
+        sha: &'a str,
+        html_url: &'a str,
+        date: Option<DateTime<Utc>>,
+    },
+    /// A line of the last search result
+    SearchLine {
+        location: &'a str,
+        line: &'a str,
+    },
+    SearchSummary {
+        commits: usize,
+    },
+    /// What goes on behind the scenes, only written in verbose mode
+    Debug {
+        message: &'a str,
+    },
+    Info {
+        message: &'a str,
+    },
+    Warning {
+        message: &'a str,
+    },
+    Error {
+        message: &'a str,
+    },
+}
+
+/// Every event is stamped with the time it happened at
+#[derive(Serialize)]
+struct Stamped<T: Serialize> {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    event: T,
+}
+
+/// Serializes an event in the given format, nothing in the human one
+pub fn format_event<T: Serialize>(format: OutputFormat, event: T) -> Option<String> {
+    let event = Stamped {
+        time: Utc::now(),
+        event,
+    };
+    let document = match format {
+        OutputFormat::Human => return None,
+        OutputFormat::Ndjson => serde_json::to_string(&event),
+    };
+    // the events only hold strings, numbers and dates
+    document.ok()
+}
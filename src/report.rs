@@ -0,0 +1,320 @@
+use crate::config::Severity;
+use crate::matcher::CommitMatch;
+use crate::notifier::{guard_fences, markdown_escape};
+use chrono::{DateTime, TimeDelta, Utc};
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::str::FromStr;
+
+/// The kind of file a report is written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    /// A standalone page, styles included
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(format!(
+                "Invalid report format {}, expected markdown or html",
+                format
+            )
+            .into()),
+        }
+    }
+}
+
+const MARKDOWN: &str = r#"# {{ title }}
+
+Generated on {{ generated }}, {{ total }} matching commit{{ "s" if total != 1 }}.
+{% for repository in repositories %}
+
+## {{ repository.name | md }}
+{% for commit in repository.commits %}
+
+### [{{ commit.short_sha }}]({{ commit.url }}) {{ commit.summary | md }}
+
+- Author: {{ commit.author | md }}
+{% if commit.date %}
+- Date: {{ commit.date }}
+{% endif %}
+- Severity: {{ commit.severity }}
+{% if commit.message_pattern %}
+- Message matches {{ commit.message_pattern | code }}
+{% endif %}
+{% for file in commit.files %}
+
+#### {{ file.filename | code }} (+{{ file.additions }} -{{ file.deletions }})
+{% if file.patterns %}
+
+Matches {% for pattern in file.patterns %}{{ pattern | code }}{{ ", " if not loop.last }}{% endfor %}
+{% endif %}
+{% if file.lines %}
+
+```diff
+{% for line in file.lines %}
+{{ line.text | fence }}
+{% endfor %}
+```
+{% endif %}
+{% endfor %}
+{% endfor %}
+{% endfor %}
+"#;
+
+const HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{ title }}</title>
+<style>
+body { font-family: sans-serif; max-width: 960px; margin: 2em auto; color: #24292f; }
+a { color: #0969da; }
+.meta { color: #57606a; }
+.severity { border-radius: 1em; padding: 0 0.6em; background: #ddf4ff; }
+.severity.high, .severity.critical { background: #ffebe9; }
+.additions { color: #1a7f37; }
+.deletions { color: #cf222e; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; }
+pre span { display: block; }
+.add { background: #dafbe1; }
+.del { background: #ffebe9; }
+.hunk { color: #8250df; }
+</style>
+</head>
+<body>
+<h1>{{ title }}</h1>
+<p class="meta">Generated on {{ generated }}, {{ total }} matching commit{{ "s" if total != 1 }}.</p>
+{% for repository in repositories %}
+<h2>{{ repository.name }}</h2>
+{% for commit in repository.commits %}
+<h3><a href="{{ commit.url }}"><code>{{ commit.short_sha }}</code></a> {{ commit.summary }}</h3>
+<p class="meta">
+  {{ commit.author }}{% if commit.date %}, {{ commit.date }}{% endif %}
+  <span class="severity {{ commit.severity }}">{{ commit.severity }}</span>
+</p>
+{% if commit.message_pattern %}
+<p>Message matches <code>{{ commit.message_pattern }}</code></p>
+{% endif %}
+{% for file in commit.files %}
+<h4><code>{{ file.filename }}</code> <span class="additions">+{{ file.additions }}</span> <span class="deletions">-{{ file.deletions }}</span></h4>
+{% if file.patterns %}
+<p>Matches {% for pattern in file.patterns %}<code>{{ pattern }}</code>{{ ", " if not loop.last }}{% endfor %}</p>
+{% endif %}
+{% if file.lines %}
+<pre>{% for line in file.lines %}<span class="{{ line.kind }}">{{ line.text }}</span>{% endfor %}</pre>
+{% endif %}
+{% endfor %}
+{% endfor %}
+{% endfor %}
+</body>
+</html>
+"#;
+
+/// A line of a diff excerpt, along with how to highlight it
+#[derive(Debug, Serialize)]
+pub struct Line<'a> {
+    /// `add`, `del`, `hunk` or `context`
+    kind: &'static str,
+    text: &'a str,
+}
+
+impl<'a> From<&'a str> for Line<'a> {
+    fn from(text: &'a str) -> Self {
+        let kind = match text.chars().next() {
+            Some('+') => "add",
+            Some('-') => "del",
+            _ if text.starts_with("@@") => "hunk",
+            _ => "context",
+        };
+        Self { kind, text }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct File<'a> {
+    filename: &'a str,
+    additions: u64,
+    deletions: u64,
+    patterns: &'a [String],
+    /// The excerpt of the patch
+    lines: Vec<Line<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Commit<'a> {
+    sha: &'a str,
+    short_sha: &'a str,
+    url: &'a str,
+    author: &'a str,
+    summary: &'a str,
+    date: Option<String>,
+    severity: Severity,
+    message_pattern: Option<&'a str>,
+    files: Vec<File<'a>>,
+}
+
+impl<'a> From<&'a CommitMatch> for Commit<'a> {
+    fn from(commit_match: &'a CommitMatch) -> Self {
+        Self {
+            sha: &commit_match.sha,
+            short_sha: commit_match.short_sha(),
+            url: &commit_match.html_url,
+            author: commit_match.author.as_deref().unwrap_or("unknown"),
+            summary: &commit_match.summary,
+            date: commit_match
+                .date
+                .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string()),
+            severity: commit_match.severity,
+            message_pattern: commit_match.message_pattern.as_deref(),
+            files: commit_match
+                .files
+                .iter()
+                .map(|file| File {
+                    filename: &file.filename,
+                    additions: file.additions,
+                    deletions: file.deletions,
+                    patterns: &file.patterns,
+                    lines: file
+                        .excerpt
+                        .iter()
+                        .flat_map(|excerpt| excerpt.lines())
+                        .map(Line::from)
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Repository<'a> {
+    name: &'a str,
+    commits: Vec<Commit<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Context<'a> {
+    title: &'a str,
+    generated: String,
+    total: usize,
+    repositories: Vec<Repository<'a>>,
+}
+
+/// Wraps a text in a Markdown code span, with more backticks around it than
+/// it has in a row
+fn code_span(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest + 1);
+    // a code span starting or ending with a backtick needs some room
+    let padding = if text.starts_with('`') || text.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{fence}{padding}{text}{padding}{fence}")
+}
+
+/// Renders the matches as a report, grouped by repository and most recent
+/// commits first
+pub fn render(
+    format: ReportFormat,
+    title: &str,
+    matches: &[CommitMatch],
+) -> Result<String, Box<dyn Error>> {
+    let mut repositories: BTreeMap<&str, Vec<&CommitMatch>> = BTreeMap::new();
+    for commit_match in matches {
+        repositories
+            .entry(&commit_match.repository)
+            .or_default()
+            .push(commit_match);
+    }
+
+    let context = Context {
+        title,
+        generated: Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+        total: matches.len(),
+        repositories: repositories
+            .into_iter()
+            .map(|(name, mut commits)| {
+                commits.sort_by_key(|commit_match| Reverse(commit_match.date));
+                Repository {
+                    name,
+                    commits: commits.into_iter().map(Commit::from).collect(),
+                }
+            })
+            .collect(),
+    };
+
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    // the extension decides whether the values are escaped
+    let (name, source) = match format {
+        ReportFormat::Markdown => ("report.md", MARKDOWN),
+        ReportFormat::Html => ("report.html", HTML),
+    };
+    env.add_filter("md", |text: String| markdown_escape(&text));
+    env.add_filter("code", |text: String| code_span(&text));
+    env.add_filter("fence", |text: String| guard_fences(&text));
+    env.add_template(name, source)?;
+    Ok(env.get_template(name)?.render(context)?)
+}
+
+/// The title of a report over a date range
+pub fn title(since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> String {
+    let format = |date: DateTime<Utc>| date.format("%Y-%m-%d").to_string();
+    // the end of the range is exclusive, its last day is the one before
+    let until = until.map(|until| until - TimeDelta::seconds(1));
+    match (since, until) {
+        (Some(since), Some(until)) => format!(
+            "Vulngrep report from {} to {}",
+            format(since),
+            format(until)
+        ),
+        (Some(since), None) => format!("Vulngrep report since {}", format(since)),
+        (None, Some(until)) => format!("Vulngrep report until {}", format(until)),
+        (None, None) => "Vulngrep report".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+
+    #[test]
+    fn escapes_the_markdown() {
+        let mut commit_match = commit_match("abc123", "mm/`slab`.c");
+        commit_match.summary = "Fix *all* <script>".to_owned();
+        commit_match.files[0].excerpt = Some("@@ -1 +1 @@\n+```\n+# heading".to_owned());
+
+        let report = render(ReportFormat::Markdown, "Report", &[commit_match]).unwrap();
+        assert!(report.contains(r"Fix \*all\* \<script\>"));
+        assert!(report.contains("#### ``mm/`slab`.c`` (+"));
+        // the only fences are the ones of the code block
+        assert_eq!(report.matches("```").count(), 2);
+    }
+
+    #[test]
+    fn wraps_code_spans() {
+        assert_eq!(code_span("mm/slab.c"), "`mm/slab.c`");
+        assert_eq!(code_span("a``b"), "```a``b```");
+        assert_eq!(code_span("`a"), "`` `a ``");
+    }
+}
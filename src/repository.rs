@@ -1,7 +1,14 @@
 #![allow(unused)]
 
+use crate::cache::CommitCache;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use octocrab::models::commits::GithubCommitStatus;
 use octocrab::models::repos::RepoCommit;
+use octocrab::models::Rate;
+use std::time::Instant;
 use thiserror::Error;
+use tracing::{debug, info, instrument, trace, warn};
 
 #[derive(Debug, Error, PartialEq)]
 pub enum GithubRepositoryError {
@@ -22,6 +29,7 @@ pub struct GithubRepository {
     owner: String,
     name: String,
     client: octocrab::Octocrab,
+    cache: Option<CommitCache>,
 }
 
 impl GithubRepository {
@@ -37,46 +45,194 @@ impl GithubRepository {
             .map_err(|_| GithubRepositoryError::InitializationFailed)?;
 
         // check if the GitHub token is valid or not
+        let started = Instant::now();
         let user = client.current().user().await;
+        metrics::request("user", user.is_ok());
         match user {
-            Ok(_) => (),
-            Err(_) => return Err(GithubRepositoryError::InvalidToken.into()),
+            Ok(user) => debug!(
+                user = %user.login,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "GitHub token checked"
+            ),
+            Err(err) => {
+                warn!(error = %err, "GitHub token rejected");
+                return Err(GithubRepositoryError::InvalidToken);
+            }
         }
 
         let (owner, name) = (owner.into(), name.into());
 
+        let started = Instant::now();
         let repo = client.repos(&owner, &name).get().await;
+        metrics::request("repository", repo.is_ok());
         match repo {
-            Ok(_) => {}
-            Err(_) => return Err(GithubRepositoryError::InvalidRepository.into()),
+            Ok(_) => debug!(
+                repository = %format!("{}/{}", owner, name),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "repository looked up"
+            ),
+            Err(err) => {
+                warn!(
+                    repository = %format!("{}/{}", owner, name),
+                    error = %err,
+                    "repository lookup failed"
+                );
+                return Err(GithubRepositoryError::InvalidRepository);
+            }
         }
 
         Ok(Self {
             owner,
             name,
             client,
+            cache: None,
         })
     }
 
-    /// Fetches all commits up to a specific hash
+    /// Looks up (and stores) detailed commits in a local cache before
+    /// downloading them from GitHub
+    pub fn with_cache(mut self, cache: Option<CommitCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Fetches all commits reachable from `head` up to a specific hash
     pub async fn fetch_commits_until(
         &self,
+        head: &str,
         target_hash: &str,
         per_page: u8,
     ) -> Result<Vec<RepoCommit>, GithubRepositoryError> {
+        // otherwise the whole history of head would be walked to find out
+        let Some(count) = self.count_commits_between(target_hash, head).await? else {
+            return Err(GithubRepositoryError::InvalidCommitHash);
+        };
+        let (all_commits, _) = self
+            .fetch_commits(Some(head), Some(target_hash), Some(count), None, per_page)
+            .await?;
+
+        if all_commits.is_empty() {
+            Err(GithubRepositoryError::InvalidCommitHash)
+        } else {
+            Ok(all_commits)
+        }
+    }
+
+    /// Fetches all commits reachable from `head` (a hash or a branch) that were
+    /// authored after a specific date
+    pub async fn fetch_commits_since(
+        &self,
+        head: &str,
+        since: DateTime<Utc>,
+        per_page: u8,
+    ) -> Result<Vec<RepoCommit>, GithubRepositoryError> {
+        let (all_commits, _) = self
+            .fetch_commits(Some(head), None, None, Some(since), per_page)
+            .await?;
+        Ok(all_commits)
+    }
+
+    /// Fetches all commits reachable from `head` (a hash or a branch) up to the
+    /// `base` hash, which may be abbreviated (same as git's `base..head`)
+    pub async fn fetch_commits_between(
+        &self,
+        base: &str,
+        head: &str,
+        per_page: u8,
+    ) -> Result<Vec<RepoCommit>, GithubRepositoryError> {
+        // otherwise the whole history of head would be walked to find out
+        let Some(count) = self.count_commits_between(base, head).await? else {
+            return Err(GithubRepositoryError::InvalidCommitHash);
+        };
+        match self
+            .fetch_commits(Some(head), Some(base), Some(count), None, per_page)
+            .await?
+        {
+            (all_commits, true) => Ok(all_commits),
+            (_, false) => Err(GithubRepositoryError::InvalidCommitHash),
+        }
+    }
+
+    /// Verifies that `base` is either `head` or one of its ancestors
+    pub async fn is_ancestor(&self, base: &str, head: &str) -> Result<bool, GithubRepositoryError> {
+        Ok(self.count_commits_between(base, head).await?.is_some())
+    }
+
+    /// How many commits `head` is ahead of `base`, `None` unless `base` is
+    /// either `head` or one of its ancestors
+    async fn count_commits_between(
+        &self,
+        base: &str,
+        head: &str,
+    ) -> Result<Option<usize>, GithubRepositoryError> {
+        let comparison = self
+            .client
+            .commits(&self.owner, &self.name)
+            .compare(base, head)
+            .per_page(1u8)
+            .send()
+            .await;
+        metrics::request("compare", comparison.is_ok());
+        match comparison {
+            Ok(comparison) => Ok(matches!(
+                comparison.status,
+                GithubCommitStatus::Ahead | GithubCommitStatus::Identical
+            )
+            .then(|| usize::try_from(comparison.ahead_by).unwrap_or_default())),
+            // one of the commits doesn't exist
+            Err(octocrab::Error::GitHub { source, .. })
+                if matches!(source.status_code.as_u16(), 404 | 422) =>
+            {
+                Ok(None)
+            }
+            Err(err) => {
+                warn!(base, head, error = %err, "failed to compare the commits");
+                Err(GithubRepositoryError::FetchCommitsFailed)
+            }
+        }
+    }
+
+    /// Walks the commit list starting at `head` (defaults to the default branch)
+    /// and downloads the details of every commit until `target_hash` is reached.
+    /// The target is expected within `max_commits` commits, give or take a page
+    /// for the commits listed out of order, the walk fails past them.
+    /// Returns the detailed commits, and whether `target_hash` has been reached
+    #[instrument(skip(self), fields(repository = %self.uri()))]
+    async fn fetch_commits(
+        &self,
+        head: Option<&str>,
+        target_hash: Option<&str>,
+        max_commits: Option<usize>,
+        since: Option<DateTime<Utc>>,
+        per_page: u8,
+    ) -> Result<(Vec<RepoCommit>, bool), GithubRepositoryError> {
         let mut all_commits = Vec::new();
         let mut page = 1u32;
+        let handler = self.client.repos(&self.owner, &self.name);
+        let started = Instant::now();
+        let mut cached_commits = 0;
 
         loop {
-            let commits = self
-                .client
-                .repos(&self.owner, &self.name)
-                .list_commits()
-                .per_page(per_page)
-                .page(page)
-                .send()
-                .await
-                .map_err(|_| GithubRepositoryError::FetchCommitsFailed)?;
+            let mut request = handler.list_commits().per_page(per_page).page(page);
+            if let Some(head) = head {
+                request = request.sha(head);
+            }
+            if let Some(since) = since {
+                request = request.since(since);
+            }
+            let page_started = Instant::now();
+            let commits = request.send().await;
+            metrics::request("list_commits", commits.is_ok());
+            let commits = commits.map_err(|err| {
+                warn!(page, error = %err, "failed to list the commits");
+                GithubRepositoryError::FetchCommitsFailed
+            })?;
+            debug!(
+                page,
+                commits = commits.items.len(),
+                elapsed_ms = page_started.elapsed().as_millis() as u64,
+                "commit list page fetched"
+            );
 
             if commits.items.is_empty() {
                 break;
@@ -84,18 +240,64 @@ impl GithubRepository {
 
             // process commits
             for commit in commits.items {
-                if commit.sha == target_hash {
-                    return Ok(all_commits);
+                if target_hash.is_some_and(|target_hash| commit.sha.starts_with(target_hash)) {
+                    info!(
+                        commits = all_commits.len(),
+                        cached_commits,
+                        pages = page,
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        "commits fetched up to the target"
+                    );
+                    return Ok((all_commits, true));
+                }
+                if max_commits.is_some_and(|max| all_commits.len() >= max + usize::from(per_page)) {
+                    warn!(
+                        commits = all_commits.len(),
+                        target = target_hash,
+                        "target not reached within the expected commits"
+                    );
+                    return Err(GithubRepositoryError::InvalidCommitHash);
                 }
 
                 let sha = &commit.sha;
-                match self.client.commits(&self.owner, &self.name).get(sha).await {
+                let cached = self
+                    .cache
+                    .as_ref()
+                    .and_then(|cache| cache.get(&self.uri(), sha));
+                if let Some(cached) = cached {
+                    trace!(sha, "commit found in the cache");
+                    cached_commits += 1;
+                    all_commits.push(cached.commit);
+                    continue;
+                }
+
+                let commit_started = Instant::now();
+                let commit_details = self.client.commits(&self.owner, &self.name).get(sha).await;
+                metrics::request("commit", commit_details.is_ok());
+                match commit_details {
                     Ok(commit_details) => {
+                        debug!(
+                            sha,
+                            files = commit_details.files.as_ref().map_or(0, Vec::len),
+                            elapsed_ms = commit_started.elapsed().as_millis() as u64,
+                            "commit details downloaded"
+                        );
+                        if let Some(cache) = &self.cache {
+                            // caching is best effort, the commit is still processed
+                            if let Err(err) = cache.put(&self.uri(), &commit_details) {
+                                warn!(sha, error = %err, "failed to cache the commit");
+                            }
+                        }
                         // add the detailed commit info to the list
                         all_commits.push(commit_details);
                     }
-                    Err(_) => {
+                    Err(err) => {
                         // request failed, but the commit is actually valid
+                        warn!(
+                            sha,
+                            error = %err,
+                            "failed to download the commit details, its files can't be matched"
+                        );
                         all_commits.push(commit);
                     }
                 }
@@ -104,15 +306,20 @@ impl GithubRepository {
             page += 1;
         }
 
-        if all_commits.is_empty() {
-            Err(GithubRepositoryError::InvalidCommitHash)
-        } else {
-            Ok(all_commits)
-        }
+        info!(
+            commits = all_commits.len(),
+            cached_commits,
+            pages = page,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "commits fetched"
+        );
+        Ok((all_commits, false))
     }
 
     /// Gets the HEAD commit of the current repository
+    #[instrument(skip(self), fields(repository = %self.uri()))]
     pub async fn get_head(&self) -> Result<RepoCommit, GithubRepositoryError> {
+        let started = Instant::now();
         let commits = self
             .client
             .repos(&self.owner, &self.name)
@@ -120,12 +327,38 @@ impl GithubRepository {
             .per_page(1)
             .page(1u32)
             .send()
-            .await
-            .map_err(|_| GithubRepositoryError::FetchCommitsFailed)?;
+            .await;
+        metrics::request("list_commits", commits.is_ok());
+        let commits = commits.map_err(|err| {
+            warn!(error = %err, "failed to fetch the HEAD commit");
+            GithubRepositoryError::FetchCommitsFailed
+        })?;
         let head_commit = commits.items.first().unwrap();
+        debug!(
+            sha = %head_commit.sha,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "HEAD commit fetched"
+        );
         Ok(head_commit.clone())
     }
 
+    /// Gets the rate limit of the REST API for a token, which doesn't count
+    /// against the limit itself
+    pub async fn rate_limit(token: &str) -> Result<Rate, GithubRepositoryError> {
+        let client = octocrab::OctocrabBuilder::new()
+            .personal_token(token.to_string())
+            .build()
+            .map_err(|_| GithubRepositoryError::InitializationFailed)?;
+        let rate_limit = client.ratelimit().get().await;
+        metrics::request("rate_limit", rate_limit.is_ok());
+        rate_limit
+            .map(|rate_limit| rate_limit.resources.core)
+            .map_err(|err| {
+                warn!(error = %err, "failed to fetch the rate limit");
+                GithubRepositoryError::FetchCommitsFailed
+            })
+    }
+
     /// Gets repository owner
     pub fn owner(&self) -> &str {
         &self.owner
@@ -0,0 +1,227 @@
+use crate::config::Severity;
+use crate::matcher::{self, CommitMatch, FileMatch};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+const INFORMATION_URI: &str = "https://github.com/w1ldb1t/vulngrep";
+/// The rule of the files that matched by path alone
+const PATH_RULE: &str = "path";
+
+/// How a severity is shown by the viewers
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Low => "note",
+        Severity::Medium => "warning",
+        Severity::High | Severity::Critical => "error",
+    }
+}
+
+/// The rules of a run, one per configured pattern
+#[derive(Default)]
+struct Rules {
+    /// Rule id of every pattern, `None` being the path alone
+    ids: HashMap<Option<String>, String>,
+    rules: Vec<Value>,
+}
+
+impl Rules {
+    /// Turns a pattern into a stable rule id, e.g. `refcount_add(*)` becomes
+    /// `pattern/refcount_add`
+    fn id(&mut self, pattern: Option<&str>) -> String {
+        let key = pattern.map(str::to_owned);
+        if let Some(id) = self.ids.get(&key) {
+            return id.clone();
+        }
+
+        let Some(pattern) = pattern else {
+            self.ids.insert(key, PATH_RULE.to_owned());
+            self.rules.push(json!({
+                "id": PATH_RULE,
+                "shortDescription": { "text": "A watched file has been changed" },
+            }));
+            return PATH_RULE.to_owned();
+        };
+
+        let base = format!("pattern/{}", matcher::slug(pattern));
+
+        // patterns that only differ by punctuation get numbered
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while self.ids.values().any(|existing| *existing == id) {
+            suffix += 1;
+            id = format!("{}-{}", base, suffix);
+        }
+
+        self.ids.insert(key, id.clone());
+        self.rules.push(json!({
+            "id": id,
+            "name": pattern,
+            "shortDescription": { "text": format!("Matches the pattern {}", pattern) },
+        }));
+        id
+    }
+}
+
+/// A location within the new version of a file
+fn location(file: &FileMatch, line: Option<u64>) -> Value {
+    let mut location = json!({
+        "physicalLocation": {
+            "artifactLocation": { "uri": file.filename, "uriBaseId": "%SRCROOT%" },
+        }
+    });
+    if let Some(line) = line.filter(|line| *line > 0) {
+        location["physicalLocation"]["region"] = json!({ "startLine": line });
+    }
+    location
+}
+
+/// The results of a single match, one per file and pattern
+fn results(commit_match: &CommitMatch, rules: &mut Rules) -> Vec<Value> {
+    // a commit gets one result per file and rule, which the fingerprint tells apart
+    let result = |rule_id: String, filename: Option<&str>, text: String| {
+        let mut fingerprint = format!(
+            "{}@{}:{}",
+            commit_match.repository, commit_match.sha, rule_id
+        );
+        if let Some(filename) = filename {
+            fingerprint = format!("{}:{}", fingerprint, filename);
+        }
+        json!({
+            "ruleId": rule_id,
+            "level": level(commit_match.severity),
+            "message": { "text": format!("{}, see {}", text, commit_match.html_url) },
+            "hostedViewerUri": commit_match.html_url,
+            "partialFingerprints": {
+                "vulngrepResult/v1": fingerprint,
+            },
+            "properties": {
+                "repository": commit_match.repository,
+                "sha": commit_match.sha,
+                "author": commit_match.author,
+                "severity": commit_match.severity,
+            },
+        })
+    };
+
+    let mut results = Vec::new();
+    if let Some(pattern) = &commit_match.message_pattern {
+        let text = format!(
+            "The message of commit {} matches {}: {}",
+            commit_match.short_sha(),
+            pattern,
+            commit_match.summary
+        );
+        results.push(result(rules.id(Some(pattern)), None, text));
+    }
+
+    for file in &commit_match.files {
+        let patterns: Vec<Option<&str>> = if file.patterns.is_empty() {
+            vec![None]
+        } else {
+            file.patterns.iter().map(|p| Some(p.as_str())).collect()
+        };
+        for pattern in patterns {
+            let text = match pattern {
+                Some(pattern) => format!(
+                    "{} matches {} in commit {}",
+                    file.filename,
+                    pattern,
+                    commit_match.short_sha()
+                ),
+                None => format!(
+                    "{} has been changed by commit {}",
+                    file.filename,
+                    commit_match.short_sha()
+                ),
+            };
+            let mut result = result(rules.id(pattern), Some(&file.filename), text);
+            let mut lines = file.lines.iter().copied();
+            result["locations"] = json!([location(file, lines.next())]);
+            let related: Vec<Value> = lines.map(|line| location(file, Some(line))).collect();
+            if !related.is_empty() {
+                result["relatedLocations"] = json!(related);
+            }
+            results.push(result);
+        }
+    }
+    results
+}
+
+/// Builds a SARIF 2.1.0 log out of the matches, with one run per repository
+pub fn report(matches: &[CommitMatch]) -> Value {
+    let mut repositories: BTreeMap<&str, Vec<&CommitMatch>> = BTreeMap::new();
+    for commit_match in matches {
+        repositories
+            .entry(&commit_match.repository)
+            .or_default()
+            .push(commit_match);
+    }
+
+    let runs: Vec<Value> = repositories
+        .into_iter()
+        .map(|(repository, matches)| {
+            let mut rules = Rules::default();
+            let results: Vec<Value> = matches
+                .into_iter()
+                .flat_map(|commit_match| results(commit_match, &mut rules))
+                .collect();
+            json!({
+                "tool": {
+                    "driver": {
+                        "name": "vulngrep",
+                        "informationUri": INFORMATION_URI,
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules.rules,
+                    }
+                },
+                "versionControlProvenance": [
+                    { "repositoryUri": format!("https://github.com/{}", repository) }
+                ],
+                "results": results,
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": SCHEMA,
+        "version": VERSION,
+        "runs": runs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::commit_match;
+
+    #[test]
+    fn fingerprints_every_result() {
+        let mut commit_match = commit_match("abc123", "mm/slab.c");
+        let mut second = commit_match.files[0].clone();
+        second.filename = "mm/slub.c".to_owned();
+        commit_match.files.push(second);
+        commit_match.files[0].patterns.push("kfree".to_owned());
+
+        let report = report(&[commit_match]);
+        let fingerprints: Vec<&str> = report["runs"][0]["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                result["partialFingerprints"]["vulngrepResult/v1"]
+                    .as_str()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            fingerprints,
+            [
+                "torvalds/linux@abc123:pattern/uaf:mm/slab.c",
+                "torvalds/linux@abc123:pattern/kfree:mm/slab.c",
+                "torvalds/linux@abc123:pattern/uaf:mm/slub.c",
+            ]
+        );
+    }
+}
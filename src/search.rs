@@ -0,0 +1,459 @@
+use crate::cache::CommitCache;
+use crate::matcher;
+use crate::store;
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use octocrab::models::repos::RepoCommit;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use wildmatch::WildMatch;
+
+/// What the index knows about a cached commit, enough to apply the filters
+/// without loading the commit itself
+#[derive(Debug, Serialize, Deserialize)]
+struct Document {
+    repository: String,
+    sha: String,
+    date: Option<DateTime<Utc>>,
+    files: Vec<String>,
+}
+
+/// Narrows down the commits a search goes over
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    /// owner/name
+    pub repository: Option<String>,
+    /// Glob that at least one of the committed files has to match
+    pub path: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// A cached commit that contains the query
+#[derive(Debug)]
+pub struct SearchHit {
+    pub repository: String,
+    pub sha: String,
+    pub html_url: String,
+    pub date: Option<DateTime<Utc>>,
+    /// Lines of the commit message that contain the query
+    pub message_lines: Vec<String>,
+    /// Files (and their patch lines) that contain the query
+    pub files: Vec<(String, Vec<String>)>,
+}
+
+/// Inverted index over the messages and patches of the cached commits
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    /// Keyed by an id of their own rather than by repository and hash, which
+    /// the postings would otherwise repeat for every token
+    documents: BTreeMap<u32, Document>,
+    postings: HashMap<String, BTreeSet<u32>>,
+    /// The id of the next indexed commit, the ids of the removed ones aren't
+    /// given again
+    next_id: u32,
+}
+
+/// Splits text into lowercase words, identifiers are kept whole
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|token| token.len() > 1)
+        .map(|token| token.to_lowercase())
+}
+
+/// Case-insensitive (ASCII) substring search
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack
+        .to_ascii_lowercase()
+        .contains(&needle.to_ascii_lowercase())
+}
+
+impl SearchFilter {
+    fn accepts(&self, document: &Document) -> bool {
+        if let Some(repository) = &self.repository {
+            if document.repository != *repository {
+                return false;
+            }
+        }
+        if let Some(since) = self.since {
+            if document.date.is_none_or(|date| date < since) {
+                return false;
+            }
+        }
+        if let Some(until) = self.until {
+            if document.date.is_none_or(|date| date >= until) {
+                return false;
+            }
+        }
+        if let Some(path) = &self.path {
+            let glob = WildMatch::new(path);
+            if !document.files.iter().any(|file| glob.matches(file)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn accepts_file(&self, filename: &str) -> bool {
+        self.path
+            .as_ref()
+            .is_none_or(|path| WildMatch::new(path).matches(filename))
+    }
+}
+
+impl SearchIndex {
+    const INDEX_FILENAME: &'static str = "index.json";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the index file path based on the operating system
+    pub fn get_index_path() -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find data directory")?;
+
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir)?;
+
+        Ok(data_dir.join(Self::INDEX_FILENAME))
+    }
+
+    /// Load the index from the default location
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let index_path = Self::get_index_path()?;
+
+        if !index_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read(index_path)?;
+        // a corrupted index is simply rebuilt from the cache
+        Ok(serde_json::from_slice(&contents).unwrap_or_default())
+    }
+
+    /// Save the index to the default location
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let index_path = Self::get_index_path()?;
+        store::write(index_path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Brings the index in sync with the cache: indexes the newly cached
+    /// commits and forgets the ones that have been removed from it. Returns
+    /// whether anything changed
+    pub fn update(&mut self, cache: &CommitCache) -> Result<bool, Box<dyn Error>> {
+        // the same commit may be cached for several repositories
+        let mut cached: BTreeSet<(String, String)> = cache.keys()?.into_iter().collect();
+
+        let mut removed = HashSet::new();
+        for (id, document) in &self.documents {
+            // whatever is left of the cached commits afterwards is new
+            if !cached.remove(&(document.repository.clone(), document.sha.clone())) {
+                removed.insert(*id);
+            }
+        }
+        if !removed.is_empty() {
+            self.documents.retain(|id, _| !removed.contains(id));
+            for ids in self.postings.values_mut() {
+                ids.retain(|id| !removed.contains(id));
+            }
+            self.postings.retain(|_, ids| !ids.is_empty());
+        }
+
+        let mut added = false;
+        for (repository, sha) in cached {
+            let Some(cached_commit) = cache.get(&repository, &sha) else {
+                continue;
+            };
+            self.insert(repository, &cached_commit.commit);
+            added = true;
+        }
+
+        Ok(added || !removed.is_empty())
+    }
+
+    /// Indexes a commit of a repository (owner/name)
+    fn insert(&mut self, repository: String, commit: &RepoCommit) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut tokens: HashSet<String> = tokenize(&commit.commit.message).collect();
+        let mut files = Vec::new();
+        for file in commit.files.iter().flatten() {
+            if let Some(patch) = &file.patch {
+                tokens.extend(tokenize(patch));
+            }
+            files.push(file.filename.clone());
+        }
+
+        for token in tokens {
+            self.postings.entry(token).or_default().insert(id);
+        }
+        self.documents.insert(
+            id,
+            Document {
+                repository,
+                sha: commit.sha.clone(),
+                date: matcher::commit_date(commit),
+                files,
+            },
+        );
+    }
+
+    /// The commits that may contain all the words of the query. Query words
+    /// can be partial, e.g. `refcount_a` finds `refcount_add`. A query without
+    /// any words (e.g. `->`) can't be narrowed down, so every commit is returned
+    fn candidates(&self, query: &str) -> BTreeSet<u32> {
+        let mut candidates: Option<BTreeSet<u32>> = None;
+        for query_token in tokenize(query) {
+            let matching: BTreeSet<u32> = self
+                .postings
+                .iter()
+                .filter(|(token, _)| token.contains(&query_token))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+        }
+        candidates.unwrap_or_else(|| self.documents.keys().copied().collect())
+    }
+
+    /// Finds the cached commits whose message or patches contain the query
+    /// (case-insensitive), newest first
+    pub fn search(
+        &self,
+        cache: &CommitCache,
+        query: &str,
+        filter: &SearchFilter,
+    ) -> Result<Vec<SearchHit>, Box<dyn Error>> {
+        let mut hits = Vec::new();
+        for id in self.candidates(query) {
+            let Some(document) = self.documents.get(&id) else {
+                continue;
+            };
+            if !filter.accepts(document) {
+                continue;
+            }
+            let Some(cached_commit) = cache.get(&document.repository, &document.sha) else {
+                continue;
+            };
+            let commit = &cached_commit.commit;
+
+            let message_lines: Vec<String> = commit
+                .commit
+                .message
+                .lines()
+                .filter(|line| contains(line, query))
+                .map(str::to_owned)
+                .collect();
+
+            let mut files = Vec::new();
+            for file in commit.files.iter().flatten() {
+                if !filter.accepts_file(&file.filename) {
+                    continue;
+                }
+                let lines: Vec<String> = file
+                    .patch
+                    .iter()
+                    .flat_map(|patch| patch.lines())
+                    .filter(|line| contains(line, query))
+                    .map(str::to_owned)
+                    .collect();
+                if !lines.is_empty() {
+                    files.push((file.filename.clone(), lines));
+                }
+            }
+
+            if message_lines.is_empty() && files.is_empty() {
+                continue;
+            }
+            hits.push(SearchHit {
+                repository: cached_commit.repository,
+                sha: commit.sha.clone(),
+                html_url: commit.html_url.clone(),
+                date: document.date,
+                message_lines,
+                files,
+            });
+        }
+
+        hits.sort_by_key(|hit| Reverse(hit.date));
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::tests::{cache, remove};
+    use crate::matcher::tests::repo_commit;
+    use std::time::Duration;
+
+    fn tokens(text: &str) -> Vec<String> {
+        tokenize(text).collect()
+    }
+
+    #[test]
+    fn keeps_identifiers_whole() {
+        assert_eq!(
+            tokens("+\trefcount_add(1, &obj->ref); // UAF"),
+            ["refcount_add", "obj", "ref", "uaf"]
+        );
+        // single characters are too common to narrow anything down
+        assert_eq!(tokens("a = b + c1"), ["c1"]);
+    }
+
+    /// An index of commits of torvalds/linux, with their message and patch
+    fn index(commits: &[(&str, &str, &str)]) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for (sha, message, patch) in commits {
+            let commit = repo_commit(sha, message, &[("mm/slab.c", Some(*patch))]);
+            index.insert("torvalds/linux".to_owned(), &commit);
+        }
+        index
+    }
+
+    fn shas(index: &SearchIndex, query: &str) -> Vec<String> {
+        index
+            .candidates(query)
+            .into_iter()
+            .map(|id| index.documents[&id].sha.clone())
+            .collect()
+    }
+
+    #[test]
+    fn finds_partial_words() {
+        let index = index(&[
+            (
+                "abc123",
+                "mm: take a reference",
+                "+\trefcount_add(1, &obj->ref);",
+            ),
+            (
+                "def456",
+                "mm: drop a reference",
+                "+\trefcount_dec(&obj->ref);",
+            ),
+        ]);
+        assert_eq!(shas(&index, "refcount_a"), ["abc123"]);
+        assert_eq!(shas(&index, "REFCOUNT"), ["abc123", "def456"]);
+        assert!(shas(&index, "kfree").is_empty());
+    }
+
+    #[test]
+    fn intersects_the_words_of_the_query() {
+        let index = index(&[
+            (
+                "abc123",
+                "mm: take a reference",
+                "+\trefcount_add(1, &obj->ref);",
+            ),
+            (
+                "def456",
+                "mm: drop a reference",
+                "+\trefcount_dec(&obj->ref);",
+            ),
+        ]);
+        assert_eq!(shas(&index, "drop refcount"), ["def456"]);
+        assert!(shas(&index, "drop refcount_add").is_empty());
+    }
+
+    #[test]
+    fn returns_every_commit_without_words() {
+        let index = index(&[
+            ("abc123", "mm: take a reference", "+\tobj->ref++;"),
+            ("def456", "mm: drop a reference", "+\tobj->ref--;"),
+        ]);
+        assert_eq!(shas(&index, "->"), ["abc123", "def456"]);
+    }
+
+    fn document(repository: &str, date: Option<&str>, files: &[&str]) -> Document {
+        Document {
+            repository: repository.to_owned(),
+            sha: "abc123".to_owned(),
+            date: date.map(|date| date.parse().unwrap()),
+            files: files.iter().map(|file| file.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn filters_the_commits() {
+        let linux = document(
+            "torvalds/linux",
+            Some("2026-10-01T12:00:00Z"),
+            &["mm/slab.c", "drivers/phy/phy-core.c"],
+        );
+        assert!(SearchFilter::default().accepts(&linux));
+
+        let repository = |repository: &str| SearchFilter {
+            repository: Some(repository.to_owned()),
+            ..Default::default()
+        };
+        assert!(repository("torvalds/linux").accepts(&linux));
+        assert!(!repository("rust-lang/rust").accepts(&linux));
+
+        let path = |path: &str| SearchFilter {
+            path: Some(path.to_owned()),
+            ..Default::default()
+        };
+        assert!(path("drivers/*").accepts(&linux));
+        assert!(!path("fs/*").accepts(&linux));
+
+        let since = |since: &str| SearchFilter {
+            since: Some(since.parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(since("2026-10-01T00:00:00Z").accepts(&linux));
+        assert!(!since("2026-10-02T00:00:00Z").accepts(&linux));
+
+        // the end of the range is exclusive
+        let until = |until: &str| SearchFilter {
+            until: Some(until.parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(until("2026-10-02T00:00:00Z").accepts(&linux));
+        assert!(!until("2026-10-01T12:00:00Z").accepts(&linux));
+
+        // a commit without date is out of any date range
+        let undated = document("torvalds/linux", None, &["mm/slab.c"]);
+        assert!(!since("2026-10-01T00:00:00Z").accepts(&undated));
+        assert!(!until("2026-10-02T00:00:00Z").accepts(&undated));
+    }
+
+    #[test]
+    fn forgets_the_commits_removed_from_the_cache() {
+        let cache = cache("search", u64::MAX, Duration::from_secs(86400));
+        let patch = Some("+\trefcount_add(1, &obj->ref);");
+        let commit = repo_commit("abc123", "mm: take a reference", &[("mm/slab.c", patch)]);
+        cache.put("torvalds/linux", &commit).unwrap();
+        cache.put("alice/linux", &commit).unwrap();
+
+        let mut index = SearchIndex::default();
+        assert!(index.update(&cache).unwrap());
+        assert_eq!(index.documents.len(), 2);
+        assert_eq!(index.postings["refcount_add"].len(), 2);
+        // nothing changed since
+        assert!(!index.update(&cache).unwrap());
+
+        remove(&cache, "alice/linux", "abc123");
+        assert!(index.update(&cache).unwrap());
+        let documents: Vec<_> = index.documents.values().collect();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].repository, "torvalds/linux");
+        assert!(index
+            .postings
+            .values()
+            .all(|ids| ids.iter().all(|id| index.documents.contains_key(id))));
+
+        remove(&cache, "torvalds/linux", "abc123");
+        assert!(index.update(&cache).unwrap());
+        assert!(index.documents.is_empty());
+        assert!(index.postings.is_empty());
+    }
+}
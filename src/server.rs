@@ -0,0 +1,600 @@
+use crate::config::AppConfig;
+use crate::history::History;
+use crate::matcher::CommitMatch;
+use crate::matches::MatchStore;
+use crate::report::Line;
+use crate::status::{RepositoryStatus, StatusStore};
+use crate::terminal::TerminalDisplay;
+use crate::triage::{TriageState, TriageStore};
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::Utc;
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::net::TcpListener;
+use tracing::warn;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Address the web UI and API are served on, `127.0.0.1:8080` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    listen: Option<String>,
+}
+
+impl ServerConfig {
+    const DEFAULT_LISTEN: &'static str = "127.0.0.1:8080";
+
+    pub fn listen(&self) -> &str {
+        self.listen.as_deref().unwrap_or(Self::DEFAULT_LISTEN)
+    }
+}
+
+const INDEX: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Vulngrep</title>
+<style>
+body { font-family: sans-serif; max-width: 960px; margin: 2em auto; color: #24292f; }
+a { color: #0969da; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #d0d7de; }
+form { margin: 1em 0; }
+.meta { color: #57606a; }
+.ok { color: #1a7f37; }
+.error { color: #cf222e; }
+.severity { border-radius: 1em; padding: 0 0.6em; background: #ddf4ff; }
+.severity.high, .severity.critical { background: #ffebe9; }
+.ignored { opacity: 0.5; }
+.additions { color: #1a7f37; }
+.deletions { color: #cf222e; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; }
+pre span { display: block; }
+.add { background: #dafbe1; }
+.del { background: #ffebe9; }
+.hunk { color: #8250df; }
+</style>
+</head>
+<body>
+<h1>Vulngrep</h1>
+<p class="meta">As of {{ generated }}, see also <a href="/api/repositories">/api/repositories</a>, <a href="/api/matches">/api/matches</a> and <a href="/api/config">/api/config</a>.</p>
+<h2>Repositories</h2>
+<table>
+<tr><th>Repository</th><th>Last check</th><th>Status</th><th>Checked up to</th><th>New matches</th></tr>
+{% for repository in repositories %}
+<tr>
+  <td><a href="/?repo={{ repository.repository }}">{{ repository.repository }}</a></td>
+  <td>{{ repository.checked[:16] | replace("T", " ") if repository.checked else "never" }}</td>
+  {% if not repository.checked %}
+  <td class="meta">not checked yet</td>
+  {% elif repository.error %}
+  <td class="error">{{ repository.error }}</td>
+  {% else %}
+  <td class="ok">ok</td>
+  {% endif %}
+  <td><code>{{ repository.checked_up_to[:7] if repository.checked_up_to }}</code></td>
+  <td>{{ repository.new_matches }}</td>
+</tr>
+{% endfor %}
+</table>
+<h2>Matches</h2>
+<form>
+  <select name="repo">
+    <option value="">All repositories</option>
+    {% for repository in repositories %}
+    <option{{ " selected" if repository.repository == query.repo }}>{{ repository.repository }}</option>
+    {% endfor %}
+  </select>
+  <select name="state">
+    <option value="">New and ignored</option>
+    <option value="new"{{ " selected" if query.state == "new" }}>New</option>
+    <option value="ignored"{{ " selected" if query.state == "ignored" }}>Ignored</option>
+  </select>
+  <input name="since" placeholder="since YYYY-MM-DD or 30d" value="{{ query.since or "" }}">
+  <button>Filter</button>
+</form>
+<p class="meta">{{ matches | length }} matching commit{{ "s" if matches | length != 1 }}.</p>
+{% for match in matches %}
+<details class="{{ match.state }}">
+<summary>
+  <a href="{{ match.html_url }}"><code>{{ match.sha[:7] }}</code></a> {{ match.summary }}
+  <span class="meta">{{ match.repository }}, {{ match.author or "unknown" }}{% if match.date %}, {{ match.date[:16] | replace("T", " ") }}{% endif %}</span>
+  <span class="severity {{ match.severity }}">{{ match.severity }}</span>
+  {% if match.state == "ignored" %}<span class="meta">ignored</span>{% endif %}
+</summary>
+{% if match.message_pattern %}
+<p>Message matches <code>{{ match.message_pattern }}</code></p>
+{% endif %}
+{% for file in match.files %}
+<h4><code>{{ file.filename }}</code> <span class="additions">+{{ file.additions }}</span> <span class="deletions">-{{ file.deletions }}</span></h4>
+{% if file.patterns %}
+<p>Matches {% for pattern in file.patterns %}<code>{{ pattern }}</code>{{ ", " if not loop.last }}{% endfor %}</p>
+{% endif %}
+{% if file.excerpt %}
+<pre>{% for line in file.excerpt | diff_lines %}<span class="{{ line.kind }}">{{ line.text }}</span>{% endfor %}</pre>
+{% endif %}
+{% endfor %}
+</details>
+{% endfor %}
+</body>
+</html>
+"#;
+
+/// An error answered as JSON, e.g. `{"error": "..."}`
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(err: impl ToString) -> Self {
+        Self(StatusCode::BAD_REQUEST, err.to_string())
+    }
+
+    fn internal(err: impl ToString) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+impl From<Box<dyn Error>> for ApiError {
+    fn from(err: Box<dyn Error>) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl From<minijinja::Error> for ApiError {
+    fn from(err: minijinja::Error) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// Filters of the listed matches, all of them optional
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MatchQuery {
+    /// `owner/name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repo: Option<String>,
+    /// A date (YYYY-MM-DD) or a duration relative to now (e.g. 30d)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    since: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    until: Option<String>,
+    /// `new` or `ignored`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    state: Option<String>,
+}
+
+impl MatchQuery {
+    /// Empty form fields are the same as missing ones
+    fn normalize(mut self) -> Self {
+        let empty = |value: &Option<String>| value.as_deref() == Some("");
+        if empty(&self.repo) {
+            self.repo = None;
+        }
+        if empty(&self.since) {
+            self.since = None;
+        }
+        if empty(&self.until) {
+            self.until = None;
+        }
+        if empty(&self.state) {
+            self.state = None;
+        }
+        self
+    }
+}
+
+/// A recorded match, along with what the user made of it
+#[derive(Debug, Serialize)]
+struct MatchEntry<'a> {
+    #[serde(flatten)]
+    commit_match: &'a CommitMatch,
+    state: TriageState,
+}
+
+/// A watched repository, and how far the watcher got with it
+#[derive(Debug, Serialize)]
+struct RepositoryEntry<'a> {
+    repository: String,
+    /// The last commit that has been checked
+    checked_up_to: Option<&'a str>,
+    #[serde(flatten)]
+    status: Option<&'a RepositoryStatus>,
+    matches: usize,
+    /// Matches that haven't been ignored
+    new_matches: usize,
+}
+
+/// When every store was last modified and how large it was, none for the
+/// missing ones
+type Stamp = Vec<Option<(SystemTime, u64)>>;
+
+/// The last snapshot read, along with the stamp of its stores
+static SNAPSHOT: Mutex<Option<(Stamp, Arc<Snapshot>)>> = Mutex::new(None);
+
+/// Everything the watcher has recorded so far, read again whenever the
+/// watcher updates the stores
+struct Snapshot {
+    config: AppConfig,
+    history: History,
+    status: StatusStore,
+    triage: TriageStore,
+    /// Most recent first
+    matches: Vec<CommitMatch>,
+}
+
+impl Snapshot {
+    fn stamp() -> Result<Stamp, Box<dyn Error>> {
+        let paths = [
+            AppConfig::get_config_path()?,
+            History::get_config_path()?,
+            StatusStore::get_config_path()?,
+            TriageStore::get_config_path()?,
+            MatchStore::get_config_path()?,
+        ];
+        Ok(paths
+            .iter()
+            .map(|path| {
+                let metadata = fs::metadata(path).ok()?;
+                Some((metadata.modified().ok()?, metadata.len()))
+            })
+            .collect())
+    }
+
+    /// The stores as they are now, only read again once one of them changed.
+    /// Should they fail to be read, the last snapshot is served instead
+    fn current() -> Result<Arc<Self>, Box<dyn Error>> {
+        let stamp = Self::stamp()?;
+        let mut cached = SNAPSHOT
+            .lock()
+            .map_err(|_| "The snapshot has been poisoned")?;
+        if let Some((cached_stamp, snapshot)) = cached.as_ref() {
+            if *cached_stamp == stamp {
+                return Ok(snapshot.clone());
+            }
+        }
+
+        match Self::load() {
+            Ok(snapshot) => {
+                let snapshot = Arc::new(snapshot);
+                *cached = Some((stamp, snapshot.clone()));
+                Ok(snapshot)
+            }
+            Err(err) => match cached.as_ref() {
+                Some((_, snapshot)) => {
+                    warn!(error = %err, "failed to reload the stores, serving the last snapshot");
+                    Ok(snapshot.clone())
+                }
+                None => Err(err),
+            },
+        }
+    }
+
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let mut matches = MatchStore::load()?.matches().to_vec();
+        // the store is in the order the matches were reported in
+        matches.reverse();
+        matches.sort_by_key(|commit_match| Reverse(commit_match.date));
+        Ok(Self {
+            config: AppConfig::load()?,
+            history: History::load()?,
+            status: StatusStore::load()?,
+            triage: TriageStore::load()?,
+            matches,
+        })
+    }
+
+    fn entry<'a>(&self, commit_match: &'a CommitMatch) -> MatchEntry<'a> {
+        MatchEntry {
+            commit_match,
+            state: self
+                .triage
+                .state(&commit_match.repository, &commit_match.sha),
+        }
+    }
+
+    fn matches(&self, query: &MatchQuery) -> Result<Vec<MatchEntry<'_>>, Box<dyn Error>> {
+        let since = query.since.as_deref().map(crate::parse_since).transpose()?;
+        let until = query.until.as_deref().map(crate::parse_until).transpose()?;
+        let state = query
+            .state
+            .as_deref()
+            .map(str::parse::<TriageState>)
+            .transpose()?;
+        // matches recorded before the date field existed have no commit date
+        // and are left out of date ranges
+        let in_range = |commit_match: &CommitMatch| match (since, until) {
+            (None, None) => true,
+            _ => commit_match.date.is_some_and(|date| {
+                since.is_none_or(|since| date >= since) && until.is_none_or(|until| date < until)
+            }),
+        };
+
+        Ok(self
+            .matches
+            .iter()
+            .filter(|commit_match| {
+                query
+                    .repo
+                    .as_ref()
+                    .is_none_or(|uri| commit_match.repository == *uri)
+            })
+            .filter(|commit_match| in_range(commit_match))
+            .map(|commit_match| self.entry(commit_match))
+            .filter(|entry| state.is_none_or(|state| entry.state == state))
+            .collect())
+    }
+
+    /// The repositories of the config, in order
+    fn repositories(&self) -> Vec<RepositoryEntry<'_>> {
+        let history = self.history.entries();
+        let mut repositories: Vec<RepositoryEntry> = Vec::new();
+        for notification in self.config.notifications() {
+            let uri = notification.repository().uri();
+            if repositories.iter().any(|entry| entry.repository == uri) {
+                continue;
+            }
+            let matches: Vec<_> = self
+                .matches
+                .iter()
+                .filter(|commit_match| commit_match.repository == uri)
+                .map(|commit_match| self.entry(commit_match))
+                .collect();
+            repositories.push(RepositoryEntry {
+                checked_up_to: history
+                    .iter()
+                    .find(|(repository, _)| *repository == uri)
+                    .map(|(_, sha)| *sha),
+                status: self.status.get(&uri),
+                matches: matches.len(),
+                new_matches: matches
+                    .iter()
+                    .filter(|entry| entry.state == TriageState::New)
+                    .count(),
+                repository: uri,
+            });
+        }
+        repositories
+    }
+}
+
+/// Keys of the config whose text is shown as it is. The text of any other key,
+/// e.g. the GitHub token, the credentials, URLs and headers of the sinks or the
+/// arguments of the commands, is hidden along with everything below the key
+const SHOWN: [&str; 51] = [
+    "interval",
+    "notifications",
+    "repository",
+    "owner",
+    "name",
+    "files",
+    "path",
+    "pattern",
+    "backfill",
+    "recipients",
+    "notify",
+    "severity",
+    "cache",
+    "enabled",
+    "max_size",
+    "max_age",
+    "notifiers",
+    "type",
+    "min_severity",
+    "summary",
+    "template",
+    "title",
+    "body",
+    "html",
+    "file",
+    "summary_title",
+    "summary_body",
+    "format",
+    "timeout",
+    "retries",
+    "backoff",
+    "sound",
+    "actions",
+    "host",
+    "port",
+    "tls",
+    "from",
+    "to",
+    "max_entries",
+    "throttle",
+    "quiet_hours",
+    "max_per_hour",
+    "max_per_repository_per_hour",
+    "logging",
+    "directory",
+    "level",
+    "rotation",
+    "max_files",
+    "metrics",
+    "server",
+    "listen",
+];
+
+/// Hides the secrets of the config, only the text of known harmless keys is
+/// shown
+fn redact(value: &mut Value) {
+    redact_within(value, true);
+}
+
+fn redact_within(value: &mut Value, shown: bool) {
+    const REDACTED: &str = "<redacted>";
+
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                redact_within(value, shown && SHOWN.contains(&key.as_str()));
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| redact_within(value, shown)),
+        Value::String(_) if !shown => *value = Value::from(REDACTED),
+        _ => (),
+    }
+}
+
+/// Renders the page of the web UI
+fn render(snapshot: &Snapshot, query: &MatchQuery) -> Result<String, ApiError> {
+    let matches = snapshot.matches(query).map_err(ApiError::bad_request)?;
+
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.add_filter("diff_lines", |excerpt: &str| {
+        excerpt
+            .lines()
+            .map(|line| minijinja::Value::from_serialize(Line::from(line)))
+            .collect::<Vec<_>>()
+    });
+    // the extension turns the escaping of the values on
+    env.add_template("index.html", INDEX)?;
+    let page = env.get_template("index.html")?.render(json!({
+        "generated": Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+        "repositories": snapshot.repositories(),
+        "matches": matches,
+        "query": query,
+    }))?;
+    Ok(page)
+}
+
+async fn index(Query(query): Query<MatchQuery>) -> Result<Html<String>, ApiError> {
+    let snapshot = Snapshot::current()?;
+    Ok(Html(render(&snapshot, &query.normalize())?))
+}
+
+async fn matches(Query(query): Query<MatchQuery>) -> Result<Response, ApiError> {
+    let snapshot = Snapshot::current()?;
+    let matches = snapshot
+        .matches(&query.normalize())
+        .map_err(ApiError::bad_request)?;
+    Ok(Json(matches).into_response())
+}
+
+async fn commit_match(
+    Path((owner, name, sha)): Path<(String, String, String)>,
+) -> Result<Response, ApiError> {
+    let snapshot = Snapshot::current()?;
+    let repository = format!("{}/{}", owner, name);
+    // abbreviated hashes are fine, as long as they are not ambiguous
+    let found: Vec<_> = snapshot
+        .matches
+        .iter()
+        .filter(|commit_match| {
+            commit_match.repository == repository && commit_match.sha.starts_with(&sha)
+        })
+        .collect();
+    match found.as_slice() {
+        [commit_match] => Ok(Json(snapshot.entry(commit_match)).into_response()),
+        [] => Err(ApiError(
+            StatusCode::NOT_FOUND,
+            format!("No match for the commit {} of {}", sha, repository),
+        )),
+        _ => Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("Ambiguous commit hash {}", sha),
+        )),
+    }
+}
+
+async fn repositories() -> Result<Response, ApiError> {
+    let snapshot = Snapshot::current()?;
+    Ok(Json(snapshot.repositories()).into_response())
+}
+
+async fn config() -> Result<Json<Value>, ApiError> {
+    let snapshot = Snapshot::current()?;
+    let mut value = serde_json::to_value(&snapshot.config).map_err(ApiError::internal)?;
+    redact(&mut value);
+    Ok(Json(value))
+}
+
+/// Serves the web UI and the JSON API over the recorded matches, until the
+/// program is interrupted. Nothing can be changed through them
+pub async fn serve(display: &TerminalDisplay, listen: &str) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|err| format!("Failed to serve the matches on {}: {}", listen, err))?;
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/api/matches", get(matches))
+        .route("/api/matches/{owner}/{name}/{sha}", get(commit_match))
+        .route("/api/repositories", get(repositories))
+        .route("/api/config", get(config));
+
+    display.display_success(&format!(
+        "Serving the matches on http://{}/",
+        listener.local_addr()?
+    ));
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_shows_the_harmless_keys() {
+        let mut config: Value = serde_yaml::from_str(
+            r#"
+github_token: ghp_secret
+interval: 1h
+notifications:
+  - repository: { owner: torvalds, name: linux }
+    pattern: [kfree]
+notifiers:
+  - type: webhook
+    url: https://hooks.slack.com/services/secret
+    headers: { Authorization: Bearer secret }
+    retries: 3
+  - type: email
+    host: smtp.example.com
+    username: alice
+    password: secret
+    to: [security@example.com]
+  - type: command
+    command: [/usr/local/bin/open-ticket, --token, secret]
+"#,
+        )
+        .unwrap();
+        redact(&mut config);
+
+        let text = config.to_string();
+        for secret in [
+            "ghp_secret",
+            "hooks.slack.com",
+            "Bearer",
+            "alice",
+            "--token",
+        ] {
+            assert!(!text.contains(secret), "{} is shown", secret);
+        }
+        assert_eq!(config["interval"], "1h");
+        assert_eq!(config["notifications"][0]["pattern"][0], "kfree");
+        assert_eq!(config["notifiers"][0]["retries"], 3);
+        assert_eq!(config["notifiers"][1]["host"], "smtp.example.com");
+        assert_eq!(config["notifiers"][1]["to"][0], "security@example.com");
+        assert_eq!(config["notifiers"][2]["command"][0], "<redacted>");
+    }
+}
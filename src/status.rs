@@ -0,0 +1,101 @@
+use crate::store;
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// The outcome of the last check of a repository by the watcher
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryStatus {
+    /// When the repository was last checked
+    pub checked: DateTime<Utc>,
+    /// When the repository was last checked without errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub succeeded: Option<DateTime<Utc>>,
+    /// Why the last check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How the checks of the watched repositories went, so that it can be looked
+/// at from outside of the watcher
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StatusStore {
+    repositories: BTreeMap<String, RepositoryStatus>,
+}
+
+impl StatusStore {
+    const CONFIG_FILENAME: &'static str = "status.yaml";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the store file path based on the operating system
+    pub fn get_config_path() -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find config directory")?;
+
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir)?;
+
+        Ok(data_dir.join(Self::CONFIG_FILENAME))
+    }
+
+    /// Load the status from the default location
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(config_path)?;
+        let store: StatusStore = serde_yaml::from_str(&contents)?;
+        Ok(store)
+    }
+
+    /// Save the status to the default location
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+        let contents = serde_yaml::to_string(self)?;
+        store::write(config_path, contents)?;
+        Ok(())
+    }
+
+    /// The status of a repository (owner/name), if it has ever been checked
+    pub fn get(&self, uri: &str) -> Option<&RepositoryStatus> {
+        self.repositories.get(uri)
+    }
+
+    /// Every repository that has been checked, sorted by owner/name
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &RepositoryStatus)> {
+        self.repositories
+            .iter()
+            .map(|(uri, status)| (uri.as_str(), status))
+    }
+
+    /// Records the outcome of a check that just happened
+    pub fn record(&mut self, uri: &str, outcome: Result<(), String>) {
+        let now = Utc::now();
+        let succeeded = self
+            .repositories
+            .get(uri)
+            .and_then(|status| status.succeeded);
+        let status = match outcome {
+            Ok(()) => RepositoryStatus {
+                checked: now,
+                succeeded: Some(now),
+                error: None,
+            },
+            Err(error) => RepositoryStatus {
+                checked: now,
+                succeeded,
+                error: Some(error),
+            },
+        };
+        self.repositories.insert(uri.to_owned(), status);
+    }
+}
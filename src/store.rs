@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// Writes a file through a temporary one renamed over it, so that a crash
+/// never leaves a truncated file behind and readers (e.g. the dashboard or the
+/// server) never see a half-written one
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid file path {}", path.display()))?;
+    // every process gets its own temporary file, they may write at once
+    let mut temp_name = file_name.to_owned();
+    temp_name.push(format!(".{}.tmp", process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    fs::write(&temp_path, contents)?;
+    if let Err(err) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("vulngrep-store-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("matches.yaml");
+        write(&path, "first").unwrap();
+        write(&path, "second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        // nothing is left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
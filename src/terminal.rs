@@ -1,182 +1,511 @@
-use console::{style, Term};
-use notify_rust::{Notification as SystemNotification, Timeout};
+use console::{style, StyledObject, Term};
+use crate::diff::{self, DiffContext, Hunk};
+use crate::output::{format_event, Event, OutputFormat};
 use crate::repository::GithubRepository;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cell::Cell;
 use std::error::Error;
+use std::fmt;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
+/// How much is shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// Matches, command results, warnings and errors only
+    Quiet,
+    #[default]
+    Normal,
+    /// Everything, including what goes on behind the scenes
+    Verbose,
+}
+
+/// The importance of a line
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Level {
+    Debug,
+    Info,
+    /// Matches and the results of commands
+    Notice,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Notice => "NOTICE",
+            Level::Warning => "WARN",
+            Level::Error => "ERROR",
+        };
+        // padded, so that the messages of a log are aligned
+        f.pad(level)
+    }
+}
+
 pub struct TerminalDisplay {
     term: Term,
+    output: OutputFormat,
+    diff: DiffContext,
+    verbosity: Verbosity,
+    /// Plain timestamped lines, for when the output isn't a terminal
+    log: bool,
+    /// Progress lines at the bottom of the terminal, that may be cleared
+    progress_lines: Cell<usize>,
+}
+
+/// Makes the text a clickable link, where the terminal allows it
+fn hyperlink(log: bool, url: &str, text: &str) -> String {
+    if log || !console::colors_enabled() {
+        return format!("{} ({})", text, url);
+    }
+    let link = format!("\x1B]8;;{}\x07{}\x1B]8;;\x07", url, text);
+    style(link).blue().underlined().to_string()
 }
 
 impl TerminalDisplay {
     pub fn new() -> Self {
+        Self::with_output(OutputFormat::default())
+    }
+
+    pub fn with_output(output: OutputFormat) -> Self {
+        let term = Term::stdout();
         Self {
-            term: Term::stdout(),
+            log: !term.is_term(),
+            term,
+            output,
+            diff: DiffContext::default(),
+            verbosity: Verbosity::default(),
+            progress_lines: Cell::new(0),
+        }
+    }
+
+    /// Sets how much of the patches of the matching files is shown
+    pub fn with_diff(mut self, diff: DiffContext) -> Self {
+        self.diff = diff;
+        self
+    }
+
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    pub fn diff_context(&self) -> DiffContext {
+        self.diff
+    }
+
+    /// Whether events are written as JSON rather than text
+    pub fn is_structured(&self) -> bool {
+        self.output != OutputFormat::Human
+    }
+
+    /// Whether lines of that level are shown
+    fn shows(&self, level: Level) -> bool {
+        match self.verbosity {
+            Verbosity::Quiet => level >= Level::Notice,
+            Verbosity::Normal => level >= Level::Info,
+            Verbosity::Verbose => true,
+        }
+    }
+
+    /// Writes an event in the structured output, returns false when the
+    /// output is meant for humans and nothing was written
+    pub fn emit<T: Serialize>(&self, event: T) -> bool {
+        match format_event(self.output, event) {
+            Some(document) => {
+                println!("{}", document);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Prints a line, indented and preceded by a symbol in a terminal, or
+    /// timestamped in a log. Returns false when the line isn't shown
+    fn print(
+        &self,
+        level: Level,
+        indent: usize,
+        symbol: Option<StyledObject<&str>>,
+        text: &str,
+    ) -> bool {
+        if !self.shows(level) || self.is_structured() {
+            return false;
+        }
+        // whatever comes next must not be cleared
+        self.progress_lines.set(0);
+
+        let line = match (self.log, symbol) {
+            (true, _) => format!(
+                "{} {:<6} {}",
+                Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+                level,
+                text
+            ),
+            (false, Some(symbol)) => format!("{:indent$}{} {}", "", symbol, text),
+            (false, None) => format!("{:indent$}{}", "", text),
+        };
+        if level == Level::Error {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+        true
+    }
+
+    /// Prints a line that is cleared once the work it describes is done
+    fn progress(&self, indent: usize, text: &str) {
+        let lines = self.progress_lines.get();
+        if self.print(Level::Info, indent, Some(style("[*]").blue().bold()), text) {
+            self.progress_lines.set(lines + 1);
         }
     }
 
+    /// Makes a clickable link in a terminal, spells the URL out otherwise
+    fn link(&self, url: &str, text: &str) -> String {
+        hyperlink(self.log, url, text)
+    }
+
+    /// The `link` filter of the templates, e.g. `{{ sha | link(url) }}`
+    pub fn link_filter(&self) -> impl Fn(String, String) -> String + Send + Sync + 'static {
+        let log = self.log;
+        move |text, url| hyperlink(log, &url, &text)
+    }
+
     pub fn config_loaded(&self) {
-        println!(
-            "{} Configuration loaded successfully",
-            style("[✓]").green().bold()
+        self.print(
+            Level::Info,
+            0,
+            Some(style("[✓]").green().bold()),
+            "Configuration loaded successfully",
         );
     }
 
+    pub fn display_success(&self, msg: &str) {
+        if !self.shows(Level::Info) || self.emit(Event::Info { message: msg }) {
+            return;
+        }
+        self.print(Level::Info, 0, Some(style("[✓]").green().bold()), msg);
+    }
+
     pub fn display_warning(&self, msg: &str) {
-        println!("{} {}", style("[!]").yellow().bold(), msg);
+        if self.emit(Event::Warning { message: msg }) {
+            return;
+        }
+        self.print(Level::Warning, 0, Some(style("[!]").yellow().bold()), msg);
+    }
+
+    /// Tells what goes on behind the scenes, in verbose mode only
+    pub fn display_debug(&self, msg: &str) {
+        if !self.shows(Level::Debug) || self.emit(Event::Debug { message: msg }) {
+            return;
+        }
+        self.print(Level::Debug, 0, Some(style("[-]").dim()), msg);
     }
 
     pub fn inspect(&self, repo: &GithubRepository) {
+        if !self.shows(Level::Info)
+            || self.emit(Event::Inspect {
+                repository: &repo.uri(),
+            })
+        {
+            return;
+        }
         let header = format!(
             "Inspecting repository {} ...",
             style(repo.uri()).white().underlined()
         );
-        println!("{} {}", style("[*]").blue().bold(), header);
+        self.progress(0, &header);
     }
 
     pub fn display_error(&self, msg: &str) {
-        eprintln!("{} {}", style("[✗]").red().bold(), msg);
+        if self.emit(Event::Error { message: msg }) {
+            return;
+        }
+        self.print(Level::Error, 0, Some(style("[✗]").red().bold()), msg);
     }
 
     pub fn repository_added(&self, repo: &GithubRepository) -> Result<(), Box<dyn Error>> {
+        if !self.shows(Level::Info)
+            || self.emit(Event::RepositoryAdded {
+                repository: &repo.uri(),
+            })
+        {
+            return Ok(());
+        }
         self.clear_lines(1)?;
-        println!(
-            "{} Repository {} has been added to the database",
-            style("[*]").blue().bold(),
+        let text = format!(
+            "Repository {} has been added to the database",
             style(repo.uri()).white().underlined()
         );
+        self.print(Level::Info, 0, Some(style("[*]").blue().bold()), &text);
         Ok(())
     }
 
+    pub fn history_record(&self, repo_uri: &str, commit_sha: &str) {
+        if self.emit(Event::History {
+            repository: repo_uri,
+            sha: commit_sha,
+        }) {
+            return;
+        }
+        let text = format!("{} {}", style(repo_uri).white().underlined(), commit_sha);
+        self.print(Level::Notice, 0, Some(style("[*]").blue().bold()), &text);
+    }
+
     pub fn downloading_commits(&self) {
-        println!(
-            "{:>4}{} Downloading new commits ...",
-            "",
-            style("[*]").blue().bold()
+        self.progress(4, "Downloading new commits ...");
+    }
+
+    pub fn backfilling_commits(&self, since: DateTime<Utc>) {
+        if !self.shows(Level::Info) || self.emit(Event::Backfill { since }) {
+            return;
+        }
+        self.progress(
+            4,
+            &format!(
+                "Backfilling commits since {} ...",
+                since.format("%Y-%m-%d %H:%M")
+            ),
         );
     }
 
-    pub fn commit_info(
-        &self,
-        commit_url: &str,
-        commit_sha: &str,
-        pattern: &str
-    ) {
-        // make the commit's hash a clickable link to the official github page
-        let commit_to_link = format!(
-            "\x1B]8;;{}\x07{}\x1B]8;;\x07",
-            commit_url,
-            commit_sha
+    pub fn scan_summary(&self, commits: usize, matching_commits: usize) {
+        if self.emit(Event::ScanSummary {
+            commits,
+            matching_commits,
+        }) {
+            return;
+        }
+        let text = format!(
+            "Scanned {} commits, {} of them matched",
+            commits,
+            style(matching_commits).white().bold()
         );
-        println!(
-            "{:>4}{} Commit SHA: {}",
-            "",
-            style("[!]").yellow().bold(),
-            style(commit_to_link).blue().underlined(),
+        self.print(Level::Notice, 0, Some(style("[✓]").green().bold()), &text);
+    }
+
+    pub fn rescanning(&self, repo_uri: &str) {
+        if !self.shows(Level::Info)
+            || self.emit(Event::Rescan {
+                repository: repo_uri,
+            })
+        {
+            return;
+        }
+        let text = format!(
+            "New matches in the cached commits of {}",
+            style(repo_uri).white().underlined()
         );
-        println!(
-            "{:>7} Pattern matched: {}",
-            "",
-            style(pattern).white().bold(),
+        self.print(Level::Info, 0, Some(style("[*]").blue().bold()), &text);
+    }
+
+    pub fn rescan_summary(&self, commits: usize, new_matches: usize) {
+        if self.emit(Event::RescanSummary {
+            commits,
+            new_matches,
+        }) {
+            return;
+        }
+        let text = format!(
+            "Evaluated {} cached commits, {} new matches",
+            commits,
+            style(new_matches).white().bold()
         );
+        self.print(Level::Notice, 0, Some(style("[✓]").green().bold()), &text);
     }
 
-    pub fn full_commit_info(
+    pub fn search_result(
         &self,
+        repo_uri: &str,
         commit_url: &str,
         commit_sha: &str,
-        filename: &str,
-        additions: u64,
-        deletions: u64,
-        patterns_matched: Vec<String>
+        date: Option<DateTime<Utc>>,
     ) {
-        // make the commit's hash a clickable link to the official github page
-        let commit_to_link = format!(
-            "\x1B]8;;{}\x07{}\x1B]8;;\x07",
-            commit_url,
-            commit_sha
-        );
-        
-        println!(
-            "{:>4}{} Commit SHA: {}",
-            "",
-            style("[!]").yellow().bold(),
-            style(commit_to_link).blue().underlined(),
+        let event = Event::SearchResult {
+            repository: repo_uri,
+            sha: commit_sha,
+            html_url: commit_url,
+            date,
+        };
+        if self.emit(event) {
+            return;
+        }
+        let date = date
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let text = format!(
+            "{} {} {}",
+            style(repo_uri).white().underlined(),
+            date,
+            self.link(commit_url, commit_sha),
         );
-        println!(
-            "{:>7} File: {file}, Additions: {additions}, Deletions: {deletions}",
-            "",
-            file = style(filename).white().bold(),
-            additions = style(additions).green().underlined(),
-            deletions = style(deletions).red().underlined(),
+        self.print(Level::Notice, 0, Some(style("[*]").blue().bold()), &text);
+    }
+
+    pub fn search_line(&self, location: &str, line: &str, query: &str) {
+        if self.emit(Event::SearchLine {
+            location,
+            line: line.trim_end(),
+        }) {
+            return;
+        }
+        // highlight every occurrence of the query within the line
+        let mut highlighted = String::new();
+        let lowercase_line = line.to_ascii_lowercase();
+        let lowercase_query = query.to_ascii_lowercase();
+        let mut last = 0;
+        for (start, _) in lowercase_line.match_indices(&lowercase_query) {
+            let end = start + query.len();
+            highlighted.push_str(&line[last..start]);
+            highlighted.push_str(&style(&line[start..end]).yellow().bold().to_string());
+            last = end;
+        }
+        highlighted.push_str(&line[last..]);
+
+        let text = format!(
+            "{}: {}",
+            style(location).white().bold(),
+            highlighted.trim_end()
         );
-        for pattern in patterns_matched {
-            println!(
-                "{:>7} Pattern matched: {}",
-                "",
-                style(pattern).white().bold(),
-            );
+        self.print(Level::Notice, 8, None, &text);
+    }
+
+    pub fn search_summary(&self, commits: usize) {
+        if self.emit(Event::SearchSummary { commits }) {
+            return;
         }
+        let text = format!("Found {} matching commits", style(commits).white().bold());
+        self.print(Level::Notice, 0, Some(style("[✓]").green().bold()), &text);
     }
 
-    pub fn commit_notification(
-        &self,
-        repo_uri: &str,
-        commit_sha: &str,
-        author: &str,
-    ) -> Result<(), Box<dyn Error>> {
-        #[cfg(all(unix))]
-        static SOUND: &str = "message-new-instant";
-        #[cfg(target_os = "windows")]
-        static SOUND: &str = "Mail";
-
-        let summary = format!("🔍 New matching commit in {0}", repo_uri);
-        let body: String = format!("👤 {0}\n🔗 {1}", author, commit_sha);
-        
-        SystemNotification::new()
-            .summary(&summary)
-            .body(&body)
-            .sound_name(SOUND)
-            .timeout(Timeout::Never)
-            .show()?;
-            
-        Ok(())
+    /// Prints the first lines about a match, the first one marked as new
+    pub fn match_title(&self, title: &str) {
+        for (index, line) in title.lines().enumerate() {
+            match index {
+                0 => self.print(Level::Notice, 4, Some(style("[!]").yellow().bold()), line),
+                _ => self.print(Level::Notice, 8, None, line),
+            };
+        }
+    }
+
+    /// Prints the lines about one of the files of a match
+    pub fn match_file(&self, text: &str) {
+        for line in text.lines() {
+            self.print(Level::Notice, 8, None, line);
+        }
+    }
+
+    /// Prints the lines of a patch responsible for a match, with the matched
+    /// parts highlighted
+    pub fn diff_hunks(&self, hunks: &[Hunk], patterns: &[String]) {
+        for hunk in hunks {
+            let start = hunk.lines.iter().find_map(|line| line.number);
+            let header = match start {
+                Some(start) => format!("@@ +{} @@", start),
+                None => "@@".to_owned(),
+            };
+            let header = match hunk.function {
+                Some(function) => {
+                    format!("{} {}", style(header).cyan(), style(function).cyan().bold())
+                }
+                None => style(header).cyan().to_string(),
+            };
+            self.print(Level::Notice, 8, None, &header);
+
+            for line in &hunk.lines {
+                let number = line
+                    .number
+                    .map(|number| number.to_string())
+                    .unwrap_or_default();
+                let color = |text: &str| match line.text.chars().next() {
+                    Some('+') => style(text.to_owned()).green(),
+                    Some('-') => style(text.to_owned()).red(),
+                    _ => style(text.to_owned()).dim(),
+                };
+
+                // highlight the first span matched by any of the patterns
+                let text = line.text.trim_end();
+                let span = patterns
+                    .iter()
+                    .filter(|_| line.matching)
+                    .find_map(|pattern| diff::matched_span(text, pattern));
+                let highlighted = match span {
+                    Some((start, end)) => format!(
+                        "{}{}{}",
+                        color(&text[..start]),
+                        style(&text[start..end]).yellow().bold().underlined(),
+                        color(&text[end..])
+                    ),
+                    None => color(text).to_string(),
+                };
+                let text = format!("{} {}", style(format!("{:>5}", number)).dim(), highlighted);
+                self.print(Level::Notice, 8, None, &text);
+            }
+        }
+    }
+
+    /// Prints a match rendered by a user-defined template
+    pub fn templated_match(&self, body: &str) {
+        for line in body.lines() {
+            self.print(Level::Notice, 4, None, line);
+        }
     }
 
     pub fn show_countdown(&self, duration: Duration) -> Result<(), Box<dyn Error>> {
+        if !self.shows(Level::Info) || self.is_structured() {
+            sleep(duration);
+            return Ok(());
+        }
+
         let start_time = Instant::now();
         let end_time = start_time + duration;
-        
-        // update every second
-        while Instant::now() < end_time {
-            let remaining = end_time - Instant::now();
+        let countdown_msg = |remaining: Duration| {
             let hours = remaining.as_secs() / 3600;
             let minutes = (remaining.as_secs() % 3600) / 60;
             let seconds = remaining.as_secs() % 60;
+            format!("Next check in {:02}:{:02}:{:02}", hours, minutes, seconds)
+        };
 
-            // format countdown message
-            let countdown_msg = format!(
-                "{} Next check in {:02}:{:02}:{:02}",
-                style("[⏰]").blue().bold(),
-                hours,
-                minutes,
-                seconds
-            );
+        if self.log {
+            // a log only gets the line once, there is nothing to redraw
+            self.print(Level::Info, 0, None, &countdown_msg(duration));
+            sleep(duration);
+            return Ok(());
+        }
+
+        // update every second
+        while Instant::now() < end_time {
+            let remaining = end_time - Instant::now();
 
             // print new countdown
-            println!("{}", countdown_msg);
+            println!(
+                "{} {}",
+                style("[⏰]").blue().bold(),
+                countdown_msg(remaining)
+            );
             // sleep for 1 second
             sleep(Duration::from_secs(1));
             // clear previous countdown
-            self.clear_lines(1)?;
+            self.term.clear_last_lines(1)?;
         }
-        
+
         Ok(())
     }
 
+    /// Clears the last progress lines, lines printed after them are never
+    /// cleared
     pub fn clear_lines(&self, count: usize) -> Result<(), Box<dyn Error>> {
+        let count = count.min(self.progress_lines.get());
+        if count == 0 || self.log || self.is_structured() {
+            return Ok(());
+        }
         self.term.clear_last_lines(count)?;
+        self.progress_lines.set(self.progress_lines.get() - count);
         Ok(())
     }
 }
@@ -185,4 +514,4 @@ impl Default for TerminalDisplay {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,112 @@
+use crate::store;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// What the user made of a matching commit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TriageState {
+    #[default]
+    New,
+    /// Not worth looking into, the commit isn't reported again
+    Ignored,
+}
+
+impl FromStr for TriageState {
+    type Err = Box<dyn Error>;
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state {
+            "new" => Ok(Self::New),
+            "ignored" => Ok(Self::Ignored),
+            _ => Err(format!("Invalid triage state {}, expected new or ignored", state).into()),
+        }
+    }
+}
+
+/// The triage state of the matching commits, kept apart from the matches so
+/// that it can be updated from outside of the watcher (e.g. a desktop popup)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TriageStore {
+    /// Keyed by `owner/name@sha`, commits in the default state are left out
+    commits: BTreeMap<String, TriageState>,
+}
+
+impl TriageStore {
+    const CONFIG_FILENAME: &'static str = "triage.yaml";
+    const LOCK_EXTENSION: &'static str = "lock";
+    const QUALIFIER: &'static str = "com";
+    const ORGANIZATION: &'static str = "wildbit";
+    const APPLICATION: &'static str = "vulngrep";
+
+    /// Get the store file path based on the operating system
+    pub fn get_config_path() -> Result<PathBuf, Box<dyn Error>> {
+        let proj_dirs = ProjectDirs::from(Self::QUALIFIER, Self::ORGANIZATION, Self::APPLICATION)
+            .ok_or("Could not find config directory")?;
+
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir)?;
+
+        Ok(data_dir.join(Self::CONFIG_FILENAME))
+    }
+
+    /// Load the triage state from the default location
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(config_path)?;
+        let store: TriageStore = serde_yaml::from_str(&contents)?;
+        Ok(store)
+    }
+
+    /// Save the triage state to the default location
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let config_path = Self::get_config_path()?;
+        let contents = serde_yaml::to_string(self)?;
+        store::write(config_path, contents)?;
+        Ok(())
+    }
+
+    fn key(repository: &str, sha: &str) -> String {
+        format!("{}@{}", repository, sha)
+    }
+
+    pub fn state(&self, repository: &str, sha: &str) -> TriageState {
+        self.commits
+            .get(&Self::key(repository, sha))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, repository: &str, sha: &str, state: TriageState) {
+        let key = Self::key(repository, sha);
+        if state == TriageState::default() {
+            self.commits.remove(&key);
+        } else {
+            self.commits.insert(key, state);
+        }
+    }
+
+    /// Updates the state of a single commit on disk. The store is locked in
+    /// the meantime, as the TUI and the desktop popups of the watcher may both
+    /// update it at the same time
+    pub fn mark(repository: &str, sha: &str, state: TriageState) -> Result<(), Box<dyn Error>> {
+        let lock_path = Self::get_config_path()?.with_extension(Self::LOCK_EXTENSION);
+        let lock = File::create(lock_path)?;
+        lock.lock()?;
+
+        let mut store = Self::load()?;
+        store.set(repository, sha, state);
+        store.save()
+        // the lock is released once the file gets closed
+    }
+}
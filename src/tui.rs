@@ -0,0 +1,639 @@
+use crate::cache::CommitCache;
+use crate::config::{AppConfig, Severity};
+use crate::diff::{self, DiffContext};
+use crate::history::History;
+use crate::matcher::CommitMatch;
+use crate::matches::MatchStore;
+use crate::status::StatusStore;
+use crate::triage::{TriageState, TriageStore};
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, List, ListItem, ListState, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the stores are read again, to pick up the work of the watcher
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const PAGE_LINES: usize = 10;
+const KEYS: &str =
+    " q quit  ↑↓ select  tab switch pane  o open  i ignore/restore  h hide ignored  r reload";
+
+/// The watcher, run in the background as a child process so that its output
+/// doesn't get in the way of the dashboard
+struct BackgroundWatcher {
+    child: Child,
+    exit: Option<ExitStatus>,
+    /// The last error event of the watcher
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl BackgroundWatcher {
+    fn spawn() -> Result<Self, Box<dyn Error>> {
+        let mut child = Command::new(env::current_exe()?)
+            .args(["--output", "ndjson"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // the events are only looked at for errors, the rest ends up in the stores
+        let error = Arc::new(Mutex::new(None));
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or("Failed to read the watcher output")?;
+        let last_error = error.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let Ok(event) = serde_json::from_str::<Value>(&line) else {
+                    continue;
+                };
+                if event["event"] == "error" {
+                    if let (Some(message), Ok(mut last_error)) =
+                        (event["message"].as_str(), last_error.lock())
+                    {
+                        *last_error = Some(message.to_owned());
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            exit: None,
+            error,
+        })
+    }
+
+    fn describe(&mut self) -> String {
+        if self.exit.is_none() {
+            self.exit = self.child.try_wait().ok().flatten();
+        }
+        let error = self.error.lock().ok().and_then(|error| error.clone());
+        match (self.exit, error) {
+            (None, None) => format!("watcher running (pid {})", self.child.id()),
+            (None, Some(error)) => format!("watcher running, last error: {}", error),
+            (Some(_), Some(error)) => format!("watcher stopped: {}", error),
+            (Some(status), None) if status.success() => "watcher done".to_owned(),
+            (Some(status), None) => format!("watcher failed ({})", status),
+        }
+    }
+}
+
+impl Drop for BackgroundWatcher {
+    fn drop(&mut self) {
+        if self.exit.is_none() {
+            // the watcher is only meant to live as long as the dashboard
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// The pane the arrow keys move in
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    Matches,
+    Diff,
+}
+
+/// A dashboard of the watched repositories and their matches
+struct Dashboard {
+    config: AppConfig,
+    cache: Option<CommitCache>,
+    history: History,
+    status: StatusStore,
+    triage: TriageStore,
+    /// Most recent first
+    matches: Vec<CommitMatch>,
+    /// Indexes of the matches that are listed
+    visible: Vec<usize>,
+    hide_ignored: bool,
+    list: ListState,
+    focus: Pane,
+    /// The details of the selected match
+    diff: Vec<Line<'static>>,
+    diff_scroll: usize,
+    watcher: Option<BackgroundWatcher>,
+    /// Outcome of the last action, shown until the next one
+    message: Option<String>,
+    /// Why the stores couldn't be read again, shown until they can
+    reload_error: Option<String>,
+    reloaded: Instant,
+}
+
+/// How long ago something happened, e.g. `5m ago`
+fn ago(date: DateTime<Utc>) -> String {
+    let seconds = (Utc::now() - date).num_seconds().max(0);
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+fn severity_style(severity: Severity) -> Style {
+    match severity {
+        Severity::Low => Style::new().blue(),
+        Severity::Medium => Style::new().yellow(),
+        Severity::High => Style::new().red(),
+        Severity::Critical => Style::new().red().bold(),
+    }
+}
+
+/// Highlights the first span of a patch line matched by any of the patterns
+fn highlight(text: String, style: Style, patterns: &[String]) -> Vec<Span<'static>> {
+    let span = patterns
+        .iter()
+        .find_map(|pattern| diff::matched_span(&text, pattern));
+    match span {
+        Some((start, end)) => vec![
+            Span::styled(text[..start].to_owned(), style),
+            Span::styled(
+                text[start..end].to_owned(),
+                Style::new()
+                    .yellow()
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            ),
+            Span::styled(text[end..].to_owned(), style),
+        ],
+        None => vec![Span::styled(text, style)],
+    }
+}
+
+impl Dashboard {
+    fn new(watcher: Option<BackgroundWatcher>) -> Result<Self, Box<dyn Error>> {
+        let config = AppConfig::load()?;
+        let cache = CommitCache::open(&config.cache())?;
+        let mut dashboard = Self {
+            config,
+            cache,
+            history: History::default(),
+            status: StatusStore::default(),
+            triage: TriageStore::default(),
+            matches: Vec::new(),
+            visible: Vec::new(),
+            hide_ignored: false,
+            list: ListState::default(),
+            focus: Pane::Matches,
+            diff: Vec::new(),
+            diff_scroll: 0,
+            watcher,
+            message: None,
+            reload_error: None,
+            reloaded: Instant::now(),
+        };
+        dashboard.reload()?;
+        Ok(dashboard)
+    }
+
+    /// Reads the stores again, keeping the selected match selected
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        let selected = self
+            .selected()
+            .map(|commit_match| (commit_match.repository.clone(), commit_match.sha.clone()));
+
+        self.history = History::load()?;
+        self.status = StatusStore::load()?;
+        self.triage = TriageStore::load()?;
+        self.matches = MatchStore::load()?.matches().to_vec();
+        // the store is in the order the matches were reported in
+        self.matches.reverse();
+        self.matches
+            .sort_by_key(|commit_match| Reverse(commit_match.date));
+        self.reloaded = Instant::now();
+
+        self.filter();
+        let index = selected.and_then(|(repository, sha)| {
+            self.visible.iter().position(|index| {
+                self.matches[*index].repository == repository && self.matches[*index].sha == sha
+            })
+        });
+        // a match that is no longer listed leaves the selection where it was
+        self.select(index.or(self.list.selected()).or(Some(0)));
+        Ok(())
+    }
+
+    /// Reads the stores again, a failure is shown in the footer rather than
+    /// closing the dashboard, the last state read staying on screen
+    fn refresh(&mut self) {
+        self.reload_error = self
+            .reload()
+            .err()
+            .map(|err| format!("Failed to reload: {}", err));
+        // a failing store isn't read again at every key press
+        self.reloaded = Instant::now();
+    }
+
+    /// Lists the matches, hiding the ignored ones if asked to
+    fn filter(&mut self) {
+        self.visible = (0..self.matches.len())
+            .filter(|index| {
+                !self.hide_ignored || self.state(&self.matches[*index]) != TriageState::Ignored
+            })
+            .collect();
+    }
+
+    fn state(&self, commit_match: &CommitMatch) -> TriageState {
+        self.triage
+            .state(&commit_match.repository, &commit_match.sha)
+    }
+
+    fn selected(&self) -> Option<&CommitMatch> {
+        self.list
+            .selected()
+            .and_then(|selected| self.visible.get(selected))
+            .map(|index| &self.matches[*index])
+    }
+
+    fn select(&mut self, selected: Option<usize>) {
+        let selected = selected.map(|selected| selected.min(self.visible.len().saturating_sub(1)));
+        if selected != self.list.selected() {
+            self.diff_scroll = 0;
+        }
+        self.list
+            .select(selected.filter(|_| !self.visible.is_empty()));
+        self.diff = self.details();
+    }
+
+    /// Moves the selection or scrolls the diff, depending on the focus
+    fn scroll(&mut self, delta: isize) {
+        match self.focus {
+            Pane::Matches => {
+                let selected = self.list.selected().unwrap_or(0);
+                self.select(Some(selected.saturating_add_signed(delta)));
+            }
+            Pane::Diff => {
+                let last = self.diff.len().saturating_sub(1);
+                self.diff_scroll = self.diff_scroll.saturating_add_signed(delta).min(last);
+            }
+        }
+    }
+
+    /// The lines of the diff pane: the commit, and the patches of its files
+    /// with the matched parts highlighted
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some(commit_match) = self.selected() else {
+            return vec![Line::from("No matches yet").dim()];
+        };
+
+        let mut lines = vec![
+            Line::from(commit_match.summary.clone()).bold(),
+            Line::from(vec![
+                Span::raw(format!("{} ", commit_match.repository)),
+                Span::raw(commit_match.short_sha().to_owned()).blue(),
+                Span::raw(format!(
+                    " by {}",
+                    commit_match.author.as_deref().unwrap_or("unknown")
+                )),
+                Span::raw(
+                    commit_match
+                        .date
+                        .map(|date| format!(", {}", date.format("%Y-%m-%d %H:%M UTC")))
+                        .unwrap_or_default(),
+                ),
+            ]),
+            Line::from(vec![
+                Span::styled(
+                    commit_match.severity.to_string(),
+                    severity_style(commit_match.severity),
+                ),
+                Span::raw(match self.state(commit_match) {
+                    TriageState::New => ", new",
+                    TriageState::Ignored => ", ignored",
+                }),
+            ]),
+        ];
+        if let Some(pattern) = &commit_match.message_pattern {
+            lines.push(Line::from(format!("Message matches {}", pattern)));
+        }
+
+        // the full patches are only around while the commit is cached
+        let patches: HashMap<String, String> = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get(&commit_match.repository, &commit_match.sha))
+            .and_then(|cached| cached.commit.files)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|file| file.patch.map(|patch| (file.filename, patch)))
+            .collect();
+
+        for file in &commit_match.files {
+            lines.push(Line::default());
+            lines.push(Line::from(vec![
+                Span::raw(file.filename.clone()).bold(),
+                Span::raw(format!(" +{}", file.additions)).green(),
+                Span::raw(format!(" -{}", file.deletions)).red(),
+            ]));
+            if !file.patterns.is_empty() {
+                lines.push(Line::from(format!(
+                    "Pattern matched: {}",
+                    file.patterns.join(", ")
+                )));
+            }
+
+            let Some(patch) = patches.get(&file.filename).or(file.excerpt.as_ref()) else {
+                continue;
+            };
+            for hunk in diff::hunks(patch, &file.patterns, DiffContext::default()) {
+                let start = hunk.lines.iter().find_map(|line| line.number);
+                let header = match start {
+                    Some(start) => format!("@@ +{} @@", start),
+                    None => "@@".to_owned(),
+                };
+                let mut header = vec![Span::raw(header).cyan()];
+                if let Some(function) = hunk.function {
+                    header.push(Span::raw(format!(" {}", function)).cyan().bold());
+                }
+                lines.push(Line::from(header));
+
+                for line in &hunk.lines {
+                    let number = line
+                        .number
+                        .map(|number| number.to_string())
+                        .unwrap_or_default();
+                    let style = match line.text.chars().next() {
+                        Some('+') => Style::new().green(),
+                        Some('-') => Style::new().red(),
+                        _ => Style::new().dim(),
+                    };
+                    // tabs would throw the layout of the pane off
+                    let text = line.text.trim_end().replace('\t', "    ");
+                    let patterns: &[String] = if line.matching { &file.patterns } else { &[] };
+                    let mut spans = vec![Span::raw(format!("{:>5} ", number)).dim()];
+                    spans.extend(highlight(text, style, patterns));
+                    lines.push(Line::from(spans));
+                }
+            }
+        }
+        lines
+    }
+
+    fn toggle_ignored(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(commit_match) = self.selected() else {
+            return Ok(());
+        };
+        let state = match self.state(commit_match) {
+            TriageState::New => TriageState::Ignored,
+            TriageState::Ignored => TriageState::New,
+        };
+        TriageStore::mark(&commit_match.repository, &commit_match.sha, state)?;
+        self.message = Some(format!(
+            "{}@{} {}",
+            commit_match.repository,
+            commit_match.short_sha(),
+            match state {
+                TriageState::New => "restored",
+                TriageState::Ignored => "ignored",
+            }
+        ));
+        self.refresh();
+        Ok(())
+    }
+
+    /// Handles a key, returns false once the dashboard should be closed
+    fn handle(&mut self, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+        self.message = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+            KeyCode::PageDown => self.scroll(PAGE_LINES as isize),
+            KeyCode::PageUp => self.scroll(-(PAGE_LINES as isize)),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.scroll(isize::MAX),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Pane::Matches => Pane::Diff,
+                    Pane::Diff => Pane::Matches,
+                };
+            }
+            KeyCode::Char('o') | KeyCode::Enter => {
+                if let Some(commit_match) = self.selected() {
+                    if let Err(err) = open::that(&commit_match.html_url) {
+                        self.message = Some(format!("Failed to open the commit: {}", err));
+                    }
+                }
+            }
+            KeyCode::Char('i') => {
+                if let Err(err) = self.toggle_ignored() {
+                    self.message = Some(format!("Failed to triage the match: {}", err));
+                }
+            }
+            KeyCode::Char('h') => {
+                self.hide_ignored = !self.hide_ignored;
+                self.refresh();
+            }
+            KeyCode::Char('r') => self.refresh(),
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let repositories = self.config.notifications().len();
+        let [repositories_area, main_area, footer_area] = Layout::vertical([
+            Constraint::Length(repositories.clamp(1, 10) as u16 + 3),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [matches_area, diff_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main_area);
+
+        self.draw_repositories(frame, repositories_area);
+        self.draw_matches(frame, matches_area);
+        self.draw_diff(frame, diff_area);
+        self.draw_footer(frame, footer_area);
+    }
+
+    fn draw_repositories(&self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .config
+            .notifications()
+            .iter()
+            .map(|notification| {
+                let uri = notification.repository().uri();
+                let status = self.status.get(&uri);
+                let checked = status
+                    .map(|status| ago(status.checked))
+                    .unwrap_or_else(|| "never".to_owned());
+                let state = match status {
+                    None => Cell::from("not checked yet").dim(),
+                    Some(status) => match &status.error {
+                        None => Cell::from("ok").green(),
+                        Some(error) => Cell::from(error.clone()).red(),
+                    },
+                };
+                let head = self
+                    .history
+                    .entries()
+                    .into_iter()
+                    .find(|(repository, _)| *repository == uri)
+                    .map(|(_, sha)| sha.get(..7).unwrap_or(sha).to_owned())
+                    .unwrap_or_default();
+                let matches = self
+                    .matches
+                    .iter()
+                    .filter(|commit_match| commit_match.repository == uri)
+                    .filter(|commit_match| self.state(commit_match) == TriageState::New)
+                    .count();
+                Row::new(vec![
+                    Cell::from(uri),
+                    Cell::from(checked),
+                    state,
+                    Cell::from(head),
+                    Cell::from(matches.to_string()),
+                ])
+            })
+            .collect();
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(2),
+                Constraint::Length(12),
+                Constraint::Fill(2),
+                Constraint::Length(14),
+                Constraint::Length(8),
+            ],
+        )
+        .header(
+            Row::new([
+                "Repository",
+                "Last check",
+                "Status",
+                "Checked up to",
+                "Matches",
+            ])
+            .bold(),
+        )
+        .block(Block::bordered().title(" Repositories "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_matches(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|index| {
+                let commit_match = &self.matches[*index];
+                let date = commit_match
+                    .date
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| "----------".to_owned());
+                let line = Line::from(vec![
+                    Span::styled(
+                        format!("{:<9}", commit_match.severity.to_string()),
+                        severity_style(commit_match.severity),
+                    ),
+                    Span::raw(format!("{} ", date)).dim(),
+                    Span::raw(format!("{} ", commit_match.repository)),
+                    Span::raw(format!("{} ", commit_match.short_sha())).blue(),
+                    Span::raw(commit_match.summary.clone()),
+                ]);
+                let ignored = self
+                    .triage
+                    .state(&commit_match.repository, &commit_match.sha)
+                    == TriageState::Ignored;
+                if ignored {
+                    ListItem::new(line.dim().crossed_out())
+                } else {
+                    ListItem::new(line)
+                }
+            })
+            .collect();
+
+        let title = if self.hide_ignored {
+            format!(" Matches ({}, ignored hidden) ", self.visible.len())
+        } else {
+            format!(" Matches ({}) ", self.visible.len())
+        };
+        let list = List::new(items)
+            .block(self.block(title, Pane::Matches))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    fn draw_diff(&self, frame: &mut Frame, area: Rect) {
+        let paragraph = Paragraph::new(self.diff.clone())
+            .block(self.block(" Diff ".to_owned(), Pane::Diff))
+            .scroll((self.diff_scroll.min(u16::MAX as usize) as u16, 0));
+        frame.render_widget(paragraph, area);
+    }
+
+    fn draw_footer(&mut self, frame: &mut Frame, area: Rect) {
+        let status = match (&self.message, &self.reload_error, &mut self.watcher) {
+            (Some(message), _, _) => message.clone(),
+            (None, Some(err), _) => err.clone(),
+            (None, None, Some(watcher)) => watcher.describe(),
+            (None, None, None) => "not watching".to_owned(),
+        };
+        let [keys_area, status_area] = Layout::horizontal([
+            Constraint::Min(0),
+            Constraint::Length(status.chars().count() as u16 + 1),
+        ])
+        .areas(area);
+        frame.render_widget(Paragraph::new(KEYS).dim(), keys_area);
+        frame.render_widget(Paragraph::new(status).right_aligned(), status_area);
+    }
+
+    /// The border of a pane, highlighted when it has the focus
+    fn block(&self, title: String, pane: Pane) -> Block<'static> {
+        let block = Block::bordered().title(title);
+        if self.focus == pane {
+            block.border_style(Style::new().fg(Color::Cyan))
+        } else {
+            block
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(POLL_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.handle(key)? {
+                        return Ok(());
+                    }
+                }
+            }
+            if self.reloaded.elapsed() >= RELOAD_INTERVAL {
+                self.refresh();
+            }
+        }
+    }
+}
+
+/// Shows the dashboard until the user quits, along with a watcher running in
+/// the background unless told otherwise
+pub fn run(watch: bool) -> Result<(), Box<dyn Error>> {
+    let watcher = if watch {
+        Some(BackgroundWatcher::spawn()?)
+    } else {
+        None
+    };
+    let mut dashboard = Dashboard::new(watcher)?;
+
+    let mut terminal = ratatui::init();
+    let result = dashboard.run(&mut terminal);
+    ratatui::restore();
+    result
+}
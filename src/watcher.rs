@@ -1,25 +1,63 @@
-use crate::config::AppConfig;
+use crate::cache::CommitCache;
+use crate::config::{self, AppConfig, Notification};
 use crate::history::History;
+use crate::logging;
+use crate::matcher::{self, CommitMatch};
+use crate::matches::MatchStore;
+use crate::metrics;
+use crate::notifier::{MatchPayload, Notifier, Notifiers, TerminalNotifier};
 use crate::repository::{GithubRepository, GithubRepositoryError};
+use crate::status::StatusStore;
 use crate::terminal::TerminalDisplay;
+use crate::triage::{TriageState, TriageStore};
+use chrono::{DateTime, Utc};
+use octocrab::models::repos::RepoCommit;
+use std::cell::RefCell;
 use std::error::Error;
 use std::rc::Rc;
-use std::time::Duration;
-use wildmatch::WildMatch;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+
+/// The part of a repository's history to scan
+pub enum ScanRange {
+    /// Commits authored after a specific date
+    Since(DateTime<Utc>),
+    /// Commits reachable from `head` but not from `base`
+    Between { base: String, head: String },
+}
 
 pub struct RepositoryWatcher {
     config: AppConfig,
     history: History,
+    cache: Option<CommitCache>,
+    matches: RefCell<MatchStore>,
+    notifiers: Notifiers,
+    status: StatusStore,
     display: Rc<TerminalDisplay>,
+    /// Flushes the log files once the watcher is done
+    _log_guard: Option<WorkerGuard>,
 }
 
 impl RepositoryWatcher {
-    /// Creates a new RepositoryWatcher instance
-    pub fn new(display: Rc<TerminalDisplay>) -> Result<Self, Box<dyn Error>> {
+    /// Creates a new RepositoryWatcher instance, `watch` tells whether it's
+    /// meant to [`run`](Self::run) rather than for a one-off command
+    pub fn new(display: Rc<TerminalDisplay>, watch: bool) -> Result<Self, Box<dyn Error>> {
+        let config = AppConfig::load()?;
+        let log_guard = config.logging().map(logging::init).transpose()?;
+        let cache = CommitCache::open(&config.cache())?;
+        // a single cycle is over as soon as it's done
+        let watching = watch && config.interval().is_some();
+        let notifiers = Notifiers::new(&config, display.clone(), watching)?;
         Ok(Self {
-            config: AppConfig::load()?,
+            config,
             history: History::load()?,
+            cache,
+            matches: RefCell::new(MatchStore::load()?),
+            notifiers,
+            status: StatusStore::load()?,
             display,
+            _log_guard: log_guard,
         })
     }
 
@@ -34,6 +72,14 @@ impl RepositoryWatcher {
             return Ok(());
         }
 
+        if let Some(metrics) = self.config.metrics() {
+            metrics::serve(metrics).await?;
+            self.display.display_success(&format!(
+                "Serving the metrics on http://{}/metrics",
+                metrics.listen()
+            ));
+        }
+
         // process repositories at least once
         self.process_repositories().await?;
 
@@ -52,30 +98,57 @@ impl RepositoryWatcher {
         Ok(())
     }
 
-    /// Creates a wildcard out of the string, and makes it inclusive
-    fn make_pattern(&self, pattern: impl Into<String>) -> WildMatch {
-        let inclusive_pattern = format!("*{}*", pattern.into());
-        WildMatch::new_case_insensitive(&inclusive_pattern.as_str())
-    }
-
     /// Goes over all repositories, and notifies the user for any matching rules
+    #[instrument(name = "cycle", skip_all)]
     async fn process_repositories(&mut self) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        info!(
+            repositories = self.config.notifications().len(),
+            "cycle started"
+        );
+        let result = self.process_notifications().await;
+        // the summaries hold matches that are already recorded, they are
+        // delivered even when the cycle stopped half-way
+        self.notifiers.flush().await;
+        result?;
+
+        self.status.save()?;
+        self.enforce_cache_retention();
+        if self.config.metrics().is_some() {
+            // failures are already logged, the previous value is kept
+            if let Ok(rate) = GithubRepository::rate_limit(self.config.token()).await {
+                metrics::rate_limit(rate);
+            }
+        }
+        metrics::cycle(started.elapsed());
+        info!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "cycle finished"
+        );
+        Ok(())
+    }
+
+    /// Checks the new commits of every repository of the config
+    async fn process_notifications(&mut self) -> Result<(), Box<dyn Error>> {
         for notification in self.config.notifications() {
             let config_rep = &notification.repository();
             let repo = match GithubRepository::new(
                 config_rep.owner(),
                 config_rep.name(),
-                &self.config.token(),
+                self.config.token(),
             )
             .await
             {
                 Ok(repo) => {
                     self.display.inspect(&repo);
-                    repo
+                    repo.with_cache(self.cache.clone())
                 }
                 Err(GithubRepositoryError::InvalidRepository) => {
                     // For an invalid repo, we should simply tell the user
                     // and move to the next one. Don't cancel the entire run.
+                    warn!(repository = %config_rep.uri(), "repository not found, skipped");
+                    self.status
+                        .record(&config_rep.uri(), Err("Repository not found".to_owned()));
                     let err = format!("Repository {} not found. Skipping it ...", config_rep.uri());
                     self.display.display_warning(err.as_str());
                     continue;
@@ -90,159 +163,407 @@ impl RepositoryWatcher {
             let head_commit = match repo.get_head().await {
                 Ok(commit) => commit,
                 Err(err) => {
+                    warn!(repository = %repo.uri(), error = %err, "repository skipped");
+                    self.status.record(&repo.uri(), Err(err.to_string()));
                     self.display.display_error(&err.to_string());
                     continue;
                 }
             };
 
             if !self.history.has(&repo) {
-                self.history.add(&repo, head_commit.sha);
+                self.history.add(&repo, head_commit.sha.clone());
                 self.history.save()?;
+                info!(repository = %repo.uri(), head = %head_commit.sha, "repository added");
                 self.display.repository_added(&repo)?;
+
+                // Optionally look for matches in the recent past of the repository
+                let since = notification
+                    .backfill()
+                    .and_then(|backfill_secs| backfill_secs.map(config::seconds_ago).transpose());
+                match since {
+                    Ok(Some(since)) => {
+                        self.backfill(&repo, notification, &head_commit.sha, since)
+                            .await?;
+                    }
+                    Ok(None) => (),
+                    Err(err) => self.display.display_warning(&err),
+                }
+                self.status.record(&repo.uri(), Ok(()));
                 continue;
             }
 
             let last_sha = self.history.find(&repo)?;
             if *last_sha == head_commit.sha {
                 self.display.clear_lines(1)?;
+                debug!(repository = %repo.uri(), head = %head_commit.sha, "no new commits");
+                self.display
+                    .display_debug(&format!("No new commits in {}", repo.uri()));
+                self.status.record(&repo.uri(), Ok(()));
                 continue;
             }
 
             self.display.downloading_commits();
             let mut matching_commit_found = false;
 
-            match repo.fetch_commits_until(last_sha.as_str(), 5).await {
+            match repo
+                .fetch_commits_until(&head_commit.sha, last_sha.as_str(), 5)
+                .await
+            {
                 Ok(commit_details) => {
+                    info!(
+                        repository = %repo.uri(),
+                        from = %last_sha,
+                        to = %head_commit.sha,
+                        commits = commit_details.len(),
+                        "new commits"
+                    );
+                    metrics::commits_evaluated(&repo.uri(), commit_details.len());
+                    self.display.display_debug(&format!(
+                        "Fetched {} new commits of {}",
+                        commit_details.len(),
+                        repo.uri()
+                    ));
                     self.history
                         .add(&repo, commit_details.first().unwrap().sha.clone());
                     self.history.save()?;
 
                     for commit in commit_details {
-                        // Figure out if a commit is of interest. A commit is of interest if:
-                        // 1) It has a matching repository-wide pattern
-                        // 2) It has a matching file path and no patterns attached to it
-                        // 3) It has a matching file path and at least one matching file pattern
-                        let mut is_commit_of_interest = false;
-
-                        if let Some(patterns) = notification.patterns() {
-                            for pattern in patterns {
-                                let wild_pattern = self.make_pattern(&pattern);
-                                if wild_pattern.matches(&commit.commit.message) {
-                                    if !matching_commit_found {
-                                        matching_commit_found = true;
-                                        self.display.clear_lines(1)?;
-                                    }
-
-                                    self.display.commit_info(
-                                        &commit.html_url,
-                                        &commit.sha,
-                                        &pattern,
-                                    );
-
-                                    if let Some(author) = &commit.author {
-                                        self.display.commit_notification(
-                                            &repo.uri(),
-                                            &commit.sha,
-                                            &author.login,
-                                        )?;
-                                    }
-
-                                    is_commit_of_interest = true;
-                                    break;
-                                }
-                            }
-                        }
-                        if is_commit_of_interest {
+                        let Some(commit_match) =
+                            matcher::evaluate(&repo.uri(), notification, &commit)
+                        else {
                             continue;
-                        }
+                        };
 
-                        if let Some(committed_files) = commit.files {
-                            for committed_file in committed_files {
-                                let mut patterns_responsible_for_hit: Vec<String> = Vec::new();
-
-                                if let Some(notification_files) = notification.files() {
-                                    for file in notification_files {
-                                        let file_path_matches = self
-                                            .make_pattern(file.path())
-                                            .matches(&committed_file.filename);
-                                        if !file_path_matches {
-                                            break;
-                                        }
-
-                                        // Is there a matching file path with no patterns?
-                                        if file_path_matches && file.pattern().is_none() {
-                                            is_commit_of_interest = true;
-                                            break;
-                                        }
-
-                                        // Is there a matching file-wide or repository-wide pattern?
-                                        if let Some(patch) = &committed_file.patch {
-                                            let patterns_list =
-                                                [&file.pattern(), &notification.patterns()];
-
-                                            for patterns in patterns_list {
-                                                if let Some(patterns) = patterns {
-                                                    if let Some(pattern) =
-                                                        patterns.iter().find(|pattern| {
-                                                            self.make_pattern(*pattern).matches(patch)
-                                                        })
-                                                    {
-                                                        patterns_responsible_for_hit
-                                                            .push(pattern.to_string());
-                                                        is_commit_of_interest = true;
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        if is_commit_of_interest {
-                                            break;
-                                        }
-                                    }
-                                }
-
-                                // Bail out if the commit is not of interest
-                                if !is_commit_of_interest {
-                                    continue;
-                                }
-
-                                if !matching_commit_found {
-                                    matching_commit_found = true;
-                                    self.display.clear_lines(1)?;
-                                }
-
-                                self.display.full_commit_info(
-                                    &commit.html_url,
-                                    &commit.sha,
-                                    &committed_file.filename,
-                                    committed_file.additions,
-                                    committed_file.deletions,
-                                    patterns_responsible_for_hit,
-                                );
-
-                                if let Some(author) = &commit.author {
-                                    self.display.commit_notification(
-                                        &repo.uri(),
-                                        &commit.sha,
-                                        &author.login,
-                                    )?;
-                                }
-
-                                is_commit_of_interest = false;
-                            }
+                        if !matching_commit_found {
+                            matching_commit_found = true;
+                            self.display.clear_lines(1)?;
                         }
+                        self.report_match(&commit_match).await?;
                     }
                 }
                 Err(error) => {
+                    warn!(repository = %repo.uri(), error = %error, "repository skipped");
+                    self.status.record(&repo.uri(), Err(error.to_string()));
                     self.display.display_error(&error.to_string());
                     continue;
                 }
             }
 
+            self.save_matches()?;
             if !matching_commit_found {
                 self.display.clear_lines(2)?;
             }
+            self.status.record(&repo.uri(), Ok(()));
         }
         Ok(())
     }
+
+    /// Trims the commit cache down to its configured size and age
+    fn enforce_cache_retention(&self) {
+        if let Some(cache) = &self.cache {
+            match cache.enforce_retention() {
+                Ok(0) => (),
+                Ok(removed) => {
+                    info!(removed, "commit cache trimmed");
+                    self.display.display_debug(&format!(
+                        "Removed {} commits from the commit cache",
+                        removed
+                    ));
+                }
+                Err(err) => {
+                    warn!(error = %err, "failed to trim the commit cache");
+                    let err = format!("Failed to clean up the commit cache: {}", err);
+                    self.display.display_warning(err.as_str());
+                }
+            }
+        }
+    }
+
+    /// Evaluates the rules over the commits of a newly added repository that
+    /// were authored after a specific date. The history is left untouched.
+    #[instrument(skip(self, repo, notification), fields(repository = %repo.uri()))]
+    async fn backfill(
+        &self,
+        repo: &GithubRepository,
+        notification: &Notification,
+        head: &str,
+        since: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.display.backfilling_commits(since);
+
+        let commits = match repo.fetch_commits_since(head, since, 5).await {
+            Ok(commits) => commits,
+            Err(error) => {
+                warn!(error = %error, "backfill failed");
+                self.display.display_error(&error.to_string());
+                return Ok(());
+            }
+        };
+
+        metrics::commits_evaluated(&repo.uri(), commits.len());
+        let mut matching_commit_found = false;
+        for commit in commits {
+            if let Some(commit_match) = matcher::evaluate(&repo.uri(), notification, &commit) {
+                if !matching_commit_found {
+                    matching_commit_found = true;
+                    self.display.clear_lines(1)?;
+                }
+                self.report_match(&commit_match).await?;
+            }
+        }
+
+        self.save_matches()?;
+        if !matching_commit_found {
+            self.display.clear_lines(1)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates the rules of a single repository over a range of its history,
+    /// without moving the last checked commit of the repository
+    #[instrument(skip(self, range))]
+    pub async fn scan(&self, uri: &str, range: ScanRange) -> Result<(), Box<dyn Error>> {
+        let notifications = self.config.notifications_for(uri);
+        let Some(first) = notifications.first() else {
+            return Err(format!("Repository {} is not part of the config", uri).into());
+        };
+
+        let config_rep = first.repository();
+        let repo =
+            GithubRepository::new(config_rep.owner(), config_rep.name(), self.config.token())
+                .await?
+                .with_cache(self.cache.clone());
+        self.display.inspect(&repo);
+        self.display.downloading_commits();
+
+        let commits = match range {
+            ScanRange::Since(since) => {
+                let head_commit = repo.get_head().await?;
+                repo.fetch_commits_since(&head_commit.sha, since, 5).await?
+            }
+            ScanRange::Between { base, head } => {
+                repo.fetch_commits_between(&base, &head, 5).await?
+            }
+        };
+        self.display.clear_lines(1)?;
+
+        let result = self.report_commits(&repo, &notifications, &commits).await;
+        // same as at the end of a cycle, whatever was held back is delivered
+        self.notifiers.flush().await;
+        let matching_commits = result?;
+
+        self.save_matches()?;
+        info!(commits = commits.len(), matching_commits, "scan finished");
+        self.display.scan_summary(commits.len(), matching_commits);
+        self.enforce_cache_retention();
+        Ok(())
+    }
+
+    /// Reports the matches of the commits, returns how many commits matched
+    async fn report_commits(
+        &self,
+        repo: &GithubRepository,
+        notifications: &[&Notification],
+        commits: &[RepoCommit],
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut matching_commits = 0;
+        for commit in commits {
+            let mut commit_matched = false;
+            for notification in notifications {
+                if let Some(commit_match) = matcher::evaluate(&repo.uri(), notification, commit) {
+                    self.report_match(&commit_match).await?;
+                    commit_matched = true;
+                }
+            }
+            if commit_matched {
+                matching_commits += 1;
+            }
+        }
+        Ok(matching_commits)
+    }
+
+    /// Evaluates the current rules over the locally cached commits, and shows
+    /// the matches that haven't been recorded before. Doesn't access the network.
+    #[instrument(skip(self))]
+    pub async fn rescan(
+        &self,
+        uri: Option<&str>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(cache) = &self.cache else {
+            return Err("The commit cache is disabled, there is nothing to rescan".into());
+        };
+
+        let mut commits: Vec<_> = cache
+            .dated_keys(uri)?
+            .into_iter()
+            .filter(|(_, _, date)| since.is_none_or(|since| date.is_some_and(|date| date >= since)))
+            .collect();
+        // group the commits by repository, newest first
+        commits.sort_by(|(repository, _, date), (other_repository, _, other_date)| {
+            repository
+                .cmp(other_repository)
+                .then_with(|| other_date.cmp(date))
+        });
+
+        let triage = TriageStore::load()?;
+        // new matches are only shown rather than delivered, they are recorded
+        // so that the next rescan leaves them out
+        let terminal = TerminalNotifier::new("terminal".to_owned(), self.display.clone(), None)?;
+        let mut new_matches = 0;
+        let mut current_repository: Option<&str> = None;
+        for (repository, sha, _) in &commits {
+            // the commits are loaded one at a time, the cache may be large
+            let Some(cached) = cache.get(repository, sha) else {
+                continue;
+            };
+            let notifications = self.config.notifications_for(repository);
+            let recorded = record_new_matches(
+                &mut self.matches.borrow_mut(),
+                &triage,
+                repository,
+                &notifications,
+                &cached.commit,
+            );
+            for commit_match in recorded {
+                if current_repository != Some(repository.as_str()) {
+                    current_repository = Some(repository.as_str());
+                    self.display.rescanning(repository);
+                }
+                if !self.display.emit(MatchPayload::from(&commit_match)) {
+                    terminal.notify(&commit_match).await?;
+                }
+                new_matches += 1;
+            }
+        }
+
+        self.save_matches()?;
+        info!(commits = commits.len(), new_matches, "rescan finished");
+        self.display.rescan_summary(commits.len(), new_matches);
+        Ok(())
+    }
+
+    /// Delivers a matching commit to the notification sinks, and records it
+    async fn report_match(&self, commit_match: &CommitMatch) -> Result<(), Box<dyn Error>> {
+        // the triage state is reloaded, as it may change outside of the watcher
+        let triage = TriageStore::load()?;
+        if is_ignored(&triage, commit_match) {
+            return Ok(());
+        }
+
+        info!(
+            repository = %commit_match.repository,
+            sha = %commit_match.sha,
+            severity = %commit_match.severity,
+            "match reported"
+        );
+        let mut rules = commit_match.patterns();
+        if commit_match
+            .files
+            .iter()
+            .any(|file| file.patterns.is_empty())
+        {
+            // files that matched by path alone
+            rules.push("path");
+        }
+        metrics::matched(&commit_match.repository, &rules);
+        // the structured output has every match, whichever sinks it is routed to
+        self.display.emit(MatchPayload::from(commit_match));
+        self.notifiers.dispatch(commit_match).await;
+
+        self.matches.borrow_mut().record(commit_match.clone());
+        Ok(())
+    }
+
+    /// Writes the matches reported since the last time to disk, once per
+    /// repository rather than once per match
+    fn save_matches(&self) -> Result<(), Box<dyn Error>> {
+        self.matches.borrow_mut().flush()
+    }
+}
+
+/// Whether the user ignored the commit of the match
+fn is_ignored(triage: &TriageStore, commit_match: &CommitMatch) -> bool {
+    let ignored = triage.state(&commit_match.repository, &commit_match.sha) == TriageState::Ignored;
+    if ignored {
+        debug!(
+            repository = %commit_match.repository,
+            sha = %commit_match.sha,
+            "match ignored by triage"
+        );
+    }
+    ignored
+}
+
+/// Evaluates the rules over a commit, and records the matches that are neither
+/// ignored by triage nor recorded before. Returns the recorded matches
+fn record_new_matches(
+    matches: &mut MatchStore,
+    triage: &TriageStore,
+    repository: &str,
+    notifications: &[&Notification],
+    commit: &RepoCommit,
+) -> Vec<CommitMatch> {
+    notifications
+        .iter()
+        .filter_map(|notification| matcher::evaluate(repository, notification, commit))
+        .filter(|commit_match| !is_ignored(triage, commit_match))
+        .filter(|commit_match| matches.record(commit_match.clone()))
+        .collect()
+}
+
+impl Drop for RepositoryWatcher {
+    fn drop(&mut self) {
+        // a cycle that failed half-way still keeps the matches it reported
+        if let Err(err) = self.matches.get_mut().flush() {
+            warn!(error = %err, "failed to save the matches");
+            let err = format!("Failed to save the matches: {}", err);
+            self.display.display_error(err.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::tests::{notification, repo_commit};
+
+    const PATCH: &str = "@@ -1 +1,2 @@\n \tlock(obj);\n+\tkfree(obj);\n";
+
+    #[test]
+    fn records_the_new_matches_once() {
+        let notifications = [
+            notification(
+                "repository: { owner: torvalds, name: linux }\n\
+                 files: [{ path: mm/, pattern: [\"kfree(*)\"] }]",
+            ),
+            notification(
+                "repository: { owner: torvalds, name: linux }\n\
+                 files: [{ path: fs/ }]",
+            ),
+        ];
+        let notifications: Vec<_> = notifications.iter().collect();
+        let commits = [
+            repo_commit("a1", "mm: fix leak", &[("mm/slab.c", Some(PATCH))]),
+            repo_commit("b2", "btrfs: cleanup", &[("fs/btrfs/sysfs.c", None)]),
+            repo_commit("c3", "net: cleanup", &[("net/core/dev.c", Some(PATCH))]),
+        ];
+        let mut matches = MatchStore::default();
+        let mut triage = TriageStore::default();
+        triage.set("torvalds/linux", "b2", TriageState::Ignored);
+
+        let rescan = |matches: &mut MatchStore| -> Vec<String> {
+            commits
+                .iter()
+                .flat_map(|commit| {
+                    record_new_matches(matches, &triage, "torvalds/linux", &notifications, commit)
+                })
+                .map(|commit_match| commit_match.sha)
+                .collect()
+        };
+        assert_eq!(rescan(&mut matches), ["a1"]);
+        assert_eq!(matches.matches().len(), 1);
+        // the matches of the first rescan aren't reported again
+        assert!(rescan(&mut matches).is_empty());
+    }
 }